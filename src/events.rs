@@ -0,0 +1,118 @@
+use super::{codec, ids, Err};
+use heapless::{consts::U64, spsc::Queue, ArrayLength, Vec};
+use nom::{InputIter, InputLength};
+
+/// An unsolicited message sent by the wifi chip, such as a wifi event or a
+/// socket callback.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub service: ids::Service,
+    pub request: u8,
+    pub msg_type: ids::MsgType,
+    /// The encoded arguments of the callback, up to 64 bytes.
+    pub data: Vec<u8, U64>,
+    /// Set if the arguments did not fit in `data`, and were cut short.
+    pub truncated: bool,
+}
+
+impl Event {
+    /// Decodes a callback message from the payload of a frame. Returns
+    /// `Err::NotOurs` if the payload is not for a callback service.
+    pub fn parse(data: &[u8]) -> Result<Self, Err<()>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.service != ids::Service::WifiCallback && hdr.service != ids::Service::BLECallback {
+            return Err(Err::NotOurs);
+        }
+
+        let mut out = Vec::new();
+        for b in data.iter_elements() {
+            if out.push(b).is_err() {
+                break;
+            }
+        }
+
+        Ok(Self {
+            service: hdr.service,
+            request: hdr.request,
+            msg_type: hdr.msg_type,
+            truncated: out.len() < data.input_len(),
+            data: out,
+        })
+    }
+}
+
+/// Describes what happens when an event arrives and the queue is full.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OverflowPolicy {
+    /// The oldest queued event is discarded to make room.
+    DropOldest,
+    /// The incoming event is discarded.
+    DropNewest,
+}
+
+/// Buffers callback events which arrive while waiting on the reply to an RPC,
+/// so they can be handled once the call is complete.
+pub struct EventQueue<N: ArrayLength<Event>> {
+    queue: Queue<Event, N>,
+    policy: OverflowPolicy,
+    overflows: u32,
+}
+
+impl<N: ArrayLength<Event>> EventQueue<N> {
+    pub fn new(policy: OverflowPolicy) -> Self {
+        Self {
+            queue: Queue::new(),
+            policy,
+            overflows: 0,
+        }
+    }
+
+    /// Queues an event, applying the overflow policy if the queue is full.
+    pub fn push(&mut self, event: Event) {
+        if let Err(event) = self.queue.enqueue(event) {
+            self.overflows = self.overflows.saturating_add(1);
+            if self.policy == OverflowPolicy::DropOldest {
+                self.queue.dequeue();
+                self.queue.enqueue(event).ok();
+            }
+        }
+    }
+
+    /// Decodes a frame payload which `RPC::parse()` reported as `Err::NotOurs`,
+    /// and queues it if it was a callback.
+    pub fn push_frame(&mut self, data: &[u8]) -> Result<(), Err<()>> {
+        self.push(Event::parse(data)?);
+        Ok(())
+    }
+
+    /// Returns the oldest queued event, if any.
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.queue.dequeue()
+    }
+
+    /// Returns the number of events which were dropped due to the queue being full.
+    pub fn overflows(&self) -> u32 {
+        self.overflows
+    }
+
+    /// Resets the overflow counter.
+    pub fn clear_overflows(&mut self) {
+        self.overflows = 0;
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
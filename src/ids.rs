@@ -32,6 +32,8 @@ pub enum Service {
     BLECallback = 13,
     Wifi = 14,
     TCPIP = 15,
+    LWIP = 16,
+    MbedTLS = 17,
     WifiCallback = 18,
     Unknown = 255,
 }
@@ -46,6 +48,8 @@ impl From<u8> for Service {
             13 => Service::BLECallback,
             14 => Service::Wifi,
             15 => Service::TCPIP,
+            16 => Service::LWIP,
+            17 => Service::MbedTLS,
             18 => Service::WifiCallback,
             _ => Service::Unknown,
         }
@@ -66,7 +70,9 @@ impl From<SystemRequest> for u8 {
     }
 }
 
-/// Wio Terminal request IDs for the Wifi service
+/// Wio Terminal request IDs for the Wifi service. eRPC numbers requests in
+/// the order they are declared in the interface, here `rpc_wifi_api` in
+/// `erpc_idl/rpc_wifi_api.erpc` of Seeed's seeed-ambd-firmware.
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[allow(unused)]
 pub enum WifiRequest {
@@ -76,8 +82,32 @@ pub enum WifiRequest {
     IsConnectedToAP = 4,
     IsUp = 5,
     GetMacAddress = 8,
+    GetAssociatedClientList = 14,
+    GetAPBSSID = 15,
+    GetRSSI = 19,
+    SetChannel = 20,
+    GetChannel = 21,
+    SetChannelPlan = 22,
+    RegisterMulticastAddress = 23,
+    UnregisterMulticastAddress = 24,
     TurnOn = 27,
     TurnOff = 28,
+    SetPowerMode = 31,
+    SetLPSDTIM = 33,
+    GetLPSDTIM = 34,
+    SetLPSLevel = 36,
+    StartAP = 38,
+    StartAPWithHiddenSSID = 39,
+    SetScanChannels = 40,
+    GetSetting = 41,
+    ConfigAutoReconnect = 45,
+    SetAutoReconnect = 46,
+    GetAutoReconnect = 47,
+    GetLastError = 48,
+    AddCustomIE = 49,
+    UpdateCustomIE = 50,
+    DelCustomIE = 51,
+    SendRawFrame = 53,
     ScanStart = 64,
     IsScanning = 65,
     ScanGetAP = 66,
@@ -90,6 +120,19 @@ impl From<WifiRequest> for u8 {
     }
 }
 
+/// Wio Terminal request IDs for the WifiCallback service
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[allow(unused)]
+pub enum WifiCallbackRequest {
+    Event = 1,
+}
+
+impl From<WifiCallbackRequest> for u8 {
+    fn from(r: WifiCallbackRequest) -> u8 {
+        r as u8
+    }
+}
+
 /// Wio Terminal request IDs for the TCPIP service
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[allow(unused)]
@@ -6,7 +6,9 @@ use no_std_net::Ipv4Addr;
 extern crate bitflags;
 
 mod codec;
+mod events;
 mod ids;
+mod rtl8720;
 
 /// Encapsulates errors that might occur when issuing or processing eRPCs.
 #[derive(Debug, Clone, PartialEq)]
@@ -24,6 +26,10 @@ pub enum Err<E> {
     RPCErr(E),
     /// Too much data was present in the response
     ResponseOverrun,
+    /// The RPC is not supported by the firmware on the wifi chip.
+    Unsupported,
+    /// No reply was received in time.
+    Timeout,
     Unknown,
 }
 
@@ -33,7 +39,35 @@ impl<E> From<nom::Err<()>> for Err<E> {
     }
 }
 
+impl<E> Err<E> {
+    /// Converts the RPC-specific error using the provided function, leaving
+    /// other errors unchanged.
+    pub fn map_rpc<F>(self, f: impl FnOnce(E) -> F) -> Err<F> {
+        match self {
+            Err::Parsing(e) => Err::Parsing(e),
+            Err::CRCMismatch => Err::CRCMismatch,
+            Err::TXErr => Err::TXErr,
+            Err::NotOurs => Err::NotOurs,
+            Err::RPCErr(e) => Err::RPCErr(f(e)),
+            Err::ResponseOverrun => Err::ResponseOverrun,
+            Err::Unsupported => Err::Unsupported,
+            Err::Timeout => Err::Timeout,
+            Err::Unknown => Err::Unknown,
+        }
+    }
+}
+
 pub use codec::{FrameHeader, Header};
+pub use events::{Event, EventQueue, OverflowPolicy};
+pub use ids::{MsgType, Service};
+pub use rtl8720::{Rtl8720, Timing};
+
+/// The maximum length of the arguments encoded by `RPC::args()`.
+pub type ArgsLen = heapless::consts::U64;
+
+/// The maximum length of an RPC message: the 8-byte header followed by up to
+/// 512 bytes of arguments, as encoded by `RPC::long_args()`.
+pub type MsgLen = heapless::consts::U520;
 
 /// Describes an RPC used by the system.
 pub trait RPC {
@@ -41,11 +75,35 @@ pub trait RPC {
     type Error;
 
     fn header(&self, seq: u32) -> Header;
-    fn args(&self, _buff: &mut heapless::Vec<u8, heapless::consts::U64>) {}
+    fn args(&self, _buff: &mut heapless::Vec<u8, ArgsLen>) {}
+
+    /// Appends the arguments to `buff`, which normally already holds the
+    /// header. RPCs whose arguments may not fit in the 64 bytes given to
+    /// `args()` implement this; by default it encodes using `args()`.
+    fn long_args(&self, buff: &mut heapless::Vec<u8, MsgLen>) {
+        let mut args = heapless::Vec::new();
+        self.args(&mut args);
+        buff.extend_from_slice(&args).ok();
+    }
+
+    /// The oldest firmware version which implements the RPC, if it is not
+    /// present in all versions.
+    fn min_version(&self) -> Option<FirmwareVersion> {
+        None
+    }
 
     fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>>;
 }
 
+/// Issues RPCs to the wifi chip, and waits for their replies. Implemented
+/// by `Rtl8720`, and used by helpers which issue a sequence of RPCs.
+pub trait Client {
+    fn call<R: RPC>(&mut self, rpc: &mut R) -> Result<R::ReturnValue, Err<R::Error>>;
+
+    /// Waits for the given number of milliseconds.
+    fn delay_ms(&mut self, ms: u32);
+}
+
 mod system_rpcs;
 mod tcpip_rpcs;
 mod wifi_rpcs;
@@ -65,8 +123,18 @@ pub enum L3Interface {
     AP = 1,
 }
 
+impl L3Interface {
+    /// Returns the name the firmware uses for the interface.
+    pub fn ifname(&self) -> &'static str {
+        match self {
+            L3Interface::Station => "wlan0",
+            L3Interface::AP => "wlan1",
+        }
+    }
+}
+
 /// Possible modes of the Wifi PHY.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u32)]
 pub enum WifiMode {
     None = 0,
@@ -75,6 +143,21 @@ pub enum WifiMode {
     StationAndAP = 3,
     Promiscuous = 4,
     P2P = 5,
+    Unknown = 0xffff,
+}
+
+impl From<u32> for WifiMode {
+    fn from(orig: u32) -> Self {
+        match orig {
+            0 => WifiMode::None,
+            1 => WifiMode::Station,
+            2 => WifiMode::AP,
+            3 => WifiMode::StationAndAP,
+            4 => WifiMode::Promiscuous,
+            5 => WifiMode::P2P,
+            _ => WifiMode::Unknown,
+        }
+    }
 }
 
 /// Describes the high-level type of a network.
@@ -126,6 +209,65 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Services implemented by the firmware on the wifi chip, as determined by
+    /// sending each a `Probe` RPC.
+    pub struct Capabilities: u32 {
+        const SYSTEM = 1;
+        const WIFI = 2;
+        const TCPIP = 4;
+        const LWIP = 8;
+        const MBEDTLS = 0x10;
+        const BLE = 0x20;
+    }
+}
+
+impl Capabilities {
+    /// Returns the capability needed to use the given service. Callback
+    /// services are not probed, so need no capability.
+    pub fn for_service(service: Service) -> Self {
+        match service {
+            Service::System => Capabilities::SYSTEM,
+            Service::Wifi => Capabilities::WIFI,
+            Service::TCPIP => Capabilities::TCPIP,
+            Service::LWIP => Capabilities::LWIP,
+            Service::MbedTLS => Capabilities::MBEDTLS,
+            Service::BLEHost | Service::BLEGap | Service::BLEGapBone => Capabilities::BLE,
+            _ => Capabilities::empty(),
+        }
+    }
+
+    /// Returns `Err::Unsupported` if the RPC targets a service which the
+    /// firmware does not implement.
+    pub fn check<R: RPC>(&self, rpc: &R) -> Result<(), Err<R::Error>> {
+        if self.contains(Self::for_service(rpc.header(0).service)) {
+            Ok(())
+        } else {
+            Err(Err::Unsupported)
+        }
+    }
+
+    /// Encodes the capabilities for persistent storage.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        self.bits.to_le_bytes()
+    }
+
+    /// Decodes capabilities previously encoded with `to_bytes()`. Unknown bits
+    /// are ignored.
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self::from_bits_truncate(u32::from_le_bytes(bytes))
+    }
+}
+
+bitflags! {
+    /// Specifies the frames a custom information element is included in.
+    pub struct IEFrames: u8 {
+        const PROBE_REQUEST = 1;
+        const PROBE_RESPONSE = 2;
+        const BEACON = 4;
+    }
+}
+
 /// Valid WPS modes.
 #[derive(Debug, Copy, Clone)]
 #[allow(dead_code)]
@@ -178,27 +320,520 @@ impl From<u32> for Band {
     }
 }
 
-/// The machine-readable network name (6-bytes).
-#[derive(Copy, Clone)]
-#[repr(packed)]
-pub struct BSSID(pub [u8; 6]);
+/// Power saving modes of the wifi radio, trading latency for battery life.
+///
+/// The firmware cannot report the active mode, so only the DTIM interval can
+/// be read back, using the `GetLPSDTIM` RPC.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PowerSave {
+    /// The radio is always on.
+    None,
+    /// The radio sleeps between beacons, waking for every DTIM.
+    Min,
+    /// The radio sleeps for as long as possible, waking every `dtim` DTIMs.
+    Max { dtim: u8 },
+}
 
-impl core::fmt::Debug for BSSID {
+impl PowerSave {
+    /// Returns the LPS mode used by the firmware.
+    pub fn lps_mode(&self) -> u8 {
+        match self {
+            PowerSave::None => 0,
+            PowerSave::Min => 1,
+            PowerSave::Max { .. } => 2,
+        }
+    }
+
+    /// Switches the radio to this mode, first setting the DTIM interval if
+    /// the mode is `Max`.
+    pub fn apply<C: Client>(self, client: &mut C) -> Result<(), Err<WifiError>> {
+        if let PowerSave::Max { dtim } = self {
+            client.call(&mut rpcs::SetLPSDTIM { dtim })?;
+        }
+        client.call(&mut rpcs::SetPowerSave { mode: self })
+    }
+}
+
+/// How deeply the chip sleeps while in a power saving mode.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum LPSLevel {
+    Normal = 0,
+    /// The chip switches to a low-speed clock while asleep.
+    LowClock = 1,
+    /// The chip powers down parts of the radio while asleep.
+    PowerGated = 2,
+}
+
+/// Whether the firmware reconnects to the access point by itself after the
+/// connection is lost.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum AutoReconnect {
+    Disabled = 0,
+    /// Reconnection is attempted a limited number of times.
+    Finite = 1,
+    /// Reconnection is attempted until it succeeds.
+    Infinite = 2,
+    Unknown = 0xff,
+}
+
+impl From<u8> for AutoReconnect {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0 => AutoReconnect::Disabled,
+            1 => AutoReconnect::Finite,
+            2 => AutoReconnect::Infinite,
+            _ => AutoReconnect::Unknown,
+        }
+    }
+}
+
+/// Regulatory domains, which determine the channels the radio may use.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Country {
+    /// Channels legal in most countries. 2.4Ghz only.
+    World,
+    /// The United States, Canada and other FCC countries.
+    US,
+    /// The European Union and other ETSI countries.
+    EU,
+    Japan,
+    /// Unrestricted: all 2.4Ghz channels are enabled. 2.4Ghz only.
+    Global,
+}
+
+const CHANNELS_5GHZ_LOW: &[u8] = &[36, 40, 44, 48, 52, 56, 60, 64];
+const CHANNELS_5GHZ_MID: &[u8] = &[100, 104, 108, 112, 116, 120, 124, 128, 132, 136, 140];
+const CHANNELS_5GHZ_HIGH: &[u8] = &[149, 153, 157, 161, 165];
+
+impl Country {
+    /// Returns the channel plan identifier used by the firmware.
+    ///
+    /// These are the combined 2.4/5Ghz `RT_CHANNEL_DOMAIN` values from
+    /// Realtek's `rtw_mlme_ext.h`, named `<2.4Ghz domain>_<5Ghz domain>`:
+    /// WORLD_NULL (0x20), FCC1_FCC1 (0x25), WORLD_ETSI1 (0x26), MKK1_MKK1
+    /// (0x27) and GLOBAL_NULL (0x41).
+    pub fn channel_plan(&self) -> u8 {
+        match self {
+            Country::World => 0x20,
+            Country::US => 0x25,
+            Country::EU => 0x26,
+            Country::Japan => 0x27,
+            Country::Global => 0x41,
+        }
+    }
+
+    /// Returns the highest legal channel in the 2.4Ghz band. Channels 1
+    /// through this value may be used.
+    pub fn max_channel_24ghz(&self) -> u8 {
+        match self {
+            Country::US => 11,
+            Country::World | Country::EU => 13,
+            Country::Japan | Country::Global => 14,
+        }
+    }
+
+    /// Returns true if the channel may be used, in either band.
+    pub fn is_legal(&self, channel: u8) -> bool {
+        if (1..=14).contains(&channel) {
+            return channel <= self.max_channel_24ghz();
+        }
+
+        // The FCC1 5Ghz domain adds the upper band to the channels of the
+        // ETSI1 and MKK1 domains. The NULL domains have no 5Ghz channels.
+        match self {
+            Country::World | Country::Global => false,
+            Country::EU | Country::Japan => {
+                CHANNELS_5GHZ_LOW.contains(&channel) || CHANNELS_5GHZ_MID.contains(&channel)
+            }
+            Country::US => {
+                CHANNELS_5GHZ_LOW.contains(&channel)
+                    || CHANNELS_5GHZ_MID.contains(&channel)
+                    || CHANNELS_5GHZ_HIGH.contains(&channel)
+            }
+        }
+    }
+
+    /// Returns the legal channels in the 5Ghz band.
+    pub fn channels_5ghz(&self) -> impl Iterator<Item = u8> + '_ {
+        CHANNELS_5GHZ_LOW
+            .iter()
+            .chain(CHANNELS_5GHZ_MID)
+            .chain(CHANNELS_5GHZ_HIGH)
+            .copied()
+            .filter(move |c| self.is_legal(*c))
+    }
+}
+
+/// Error codes returned by the wifi driver on the RTL8720.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WifiError {
+    Pending,
+    Timeout,
+    PartialResults,
+    InvalidKey,
+    DoesNotExist,
+    NotAuthenticated,
+    NotKeyed,
+    IoctlFail,
+    BufferUnavailableTemporary,
+    BufferUnavailablePermanent,
+    WpsPbcOverlap,
+    ConnectionLost,
+    Generic,
+    BadArg,
+    BadOption,
+    NotUp,
+    NotDown,
+    NotAP,
+    NotStation,
+    BadKeyIndex,
+    RadioOff,
+    NotBandLocked,
+    NoClock,
+    BadRateSet,
+    BadBand,
+    BufferTooShort,
+    BufferTooLong,
+    Busy,
+    NotAssociated,
+    BadSSIDLen,
+    OutOfRangeChannel,
+    BadChannel,
+    BadAddress,
+    NoResource,
+    Unsupported,
+    BadLength,
+    NotReady,
+    NotPermitted,
+    NoMemory,
+    Associated,
+    NotInRange,
+    NotFound,
+    Other(i32),
+}
+
+impl From<i32> for WifiError {
+    fn from(orig: i32) -> Self {
+        match orig {
+            1 => WifiError::Pending,
+            2 => WifiError::Timeout,
+            3 => WifiError::PartialResults,
+            4 => WifiError::InvalidKey,
+            5 => WifiError::DoesNotExist,
+            6 => WifiError::NotAuthenticated,
+            7 => WifiError::NotKeyed,
+            8 => WifiError::IoctlFail,
+            9 => WifiError::BufferUnavailableTemporary,
+            10 => WifiError::BufferUnavailablePermanent,
+            11 => WifiError::WpsPbcOverlap,
+            12 => WifiError::ConnectionLost,
+            -1 => WifiError::Generic,
+            -2 => WifiError::BadArg,
+            -3 => WifiError::BadOption,
+            -4 => WifiError::NotUp,
+            -5 => WifiError::NotDown,
+            -6 => WifiError::NotAP,
+            -7 => WifiError::NotStation,
+            -8 => WifiError::BadKeyIndex,
+            -9 => WifiError::RadioOff,
+            -10 => WifiError::NotBandLocked,
+            -11 => WifiError::NoClock,
+            -12 => WifiError::BadRateSet,
+            -13 => WifiError::BadBand,
+            -14 => WifiError::BufferTooShort,
+            -15 => WifiError::BufferTooLong,
+            -16 => WifiError::Busy,
+            -17 => WifiError::NotAssociated,
+            -18 => WifiError::BadSSIDLen,
+            -19 => WifiError::OutOfRangeChannel,
+            -20 => WifiError::BadChannel,
+            -21 => WifiError::BadAddress,
+            -22 => WifiError::NoResource,
+            -23 => WifiError::Unsupported,
+            -24 => WifiError::BadLength,
+            -25 => WifiError::NotReady,
+            -26 => WifiError::NotPermitted,
+            -27 => WifiError::NoMemory,
+            -28 => WifiError::Associated,
+            -29 => WifiError::NotInRange,
+            -30 => WifiError::NotFound,
+            _ => WifiError::Other(orig),
+        }
+    }
+}
+
+/// Reasons a connection attempt can fail.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConnectError {
+    /// The password (or key) was rejected.
+    WrongPassword,
+    /// No access point with the SSID / BSSID was found.
+    APNotFound,
+    /// The attempt did not complete in time.
+    Timeout,
+    /// Authentication with the access point failed.
+    AuthenticationFailed,
+    /// The access point rejected the association.
+    AssociationRejected,
+    /// The SSID, password or security settings were invalid.
+    InvalidArgument,
+    /// Another operation, such as a scan, is in progress.
+    Busy,
+    /// The wifi radio is off.
+    RadioOff,
+    /// The firmware ran out of memory.
+    NoMemory,
+    /// The firmware reported an unspecified failure.
+    Failed,
+    /// Any other error reported by the wifi driver.
+    Other(WifiError),
+}
+
+impl From<i32> for ConnectError {
+    fn from(orig: i32) -> Self {
+        match WifiError::from(orig) {
+            WifiError::InvalidKey | WifiError::BadKeyIndex => ConnectError::WrongPassword,
+            WifiError::DoesNotExist | WifiError::NotFound | WifiError::NotInRange => {
+                ConnectError::APNotFound
+            }
+            WifiError::Timeout => ConnectError::Timeout,
+            WifiError::NotAuthenticated | WifiError::NotKeyed => ConnectError::AuthenticationFailed,
+            WifiError::NotAssociated | WifiError::ConnectionLost => {
+                ConnectError::AssociationRejected
+            }
+            WifiError::BadArg | WifiError::BadOption | WifiError::BadSSIDLen => {
+                ConnectError::InvalidArgument
+            }
+            WifiError::Busy | WifiError::Pending => ConnectError::Busy,
+            WifiError::RadioOff | WifiError::NotUp => ConnectError::RadioOff,
+            WifiError::NoMemory | WifiError::NoResource => ConnectError::NoMemory,
+            WifiError::Generic => ConnectError::Failed,
+            e => ConnectError::Other(e),
+        }
+    }
+}
+
+/// The reason the last connection attempt failed, as returned by the
+/// `GetLastError` RPC. These follow the `error_flag` values in Realtek's
+/// `wifi_conf.h`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LastError {
+    None,
+    /// No network with the SSID was found.
+    NoNetwork,
+    ConnectFailed,
+    WrongPassword,
+    /// The 4-way handshake did not complete in time, which usually means the
+    /// password was wrong.
+    HandshakeTimeout,
+    /// No address could be obtained using DHCP.
+    DHCPFailed,
+    AuthFailed,
+    AssocFailed,
+    /// The access point deauthenticated or disassociated us.
+    Deauthenticated,
+    Unknown(i32),
+}
+
+impl From<i32> for LastError {
+    fn from(orig: i32) -> Self {
+        match orig {
+            0 => LastError::None,
+            1 => LastError::NoNetwork,
+            2 => LastError::ConnectFailed,
+            3 => LastError::WrongPassword,
+            4 => LastError::HandshakeTimeout,
+            5 => LastError::DHCPFailed,
+            6 => LastError::AuthFailed,
+            7 => LastError::AssocFailed,
+            8 => LastError::Deauthenticated,
+            _ => LastError::Unknown(orig),
+        }
+    }
+}
+
+/// The reason an access point or station gave for ending a connection, as
+/// carried in deauthentication and disassociation frames. These are the
+/// reason codes of IEEE 802.11-2016, table 9-45.
+///
+/// The firmware neither reports these codes nor offers a way to capture the
+/// frames carrying them. The only diagnostic it provides is `GetLastError`,
+/// which returns a `LastError`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DisconnectReason {
+    Unspecified,
+    /// The previous authentication is no longer valid.
+    AuthExpired,
+    /// The sender is leaving the network.
+    AuthLeave,
+    /// Disassociated due to inactivity.
+    AssocExpired,
+    /// The access point has too many associated stations.
+    AssocTooMany,
+    NotAuthenticated,
+    NotAssociated,
+    AssocLeave,
+    /// Invalid information element, such as a mismatched RSN element.
+    InvalidIE,
+    /// A message integrity check failure.
+    MICFailure,
+    /// The 4-way handshake did not complete in time, which usually means the
+    /// password was wrong.
+    HandshakeTimeout,
+    GroupKeyHandshakeTimeout,
+    Other(u16),
+}
+
+impl From<u16> for DisconnectReason {
+    fn from(orig: u16) -> Self {
+        match orig {
+            1 => DisconnectReason::Unspecified,
+            2 => DisconnectReason::AuthExpired,
+            3 => DisconnectReason::AuthLeave,
+            4 => DisconnectReason::AssocExpired,
+            5 => DisconnectReason::AssocTooMany,
+            6 => DisconnectReason::NotAuthenticated,
+            7 => DisconnectReason::NotAssociated,
+            8 => DisconnectReason::AssocLeave,
+            13 => DisconnectReason::InvalidIE,
+            14 => DisconnectReason::MICFailure,
+            15 => DisconnectReason::HandshakeTimeout,
+            16 => DisconnectReason::GroupKeyHandshakeTimeout,
+            _ => DisconnectReason::Other(orig),
+        }
+    }
+}
+
+impl DisconnectReason {
+    /// Decodes the reason from a deauthentication or disassociation frame,
+    /// which must come from elsewhere, such as a separate capture device.
+    /// Returns None for other frames.
+    pub fn from_frame(frame: &[u8]) -> Option<Self> {
+        // The reason code follows the 24-byte management frame header.
+        if frame.len() < 26 || frame[0] & 0x0c != 0 {
+            return None;
+        }
+        match frame[0] >> 4 {
+            10 | 12 => Some(u16::from_le_bytes([frame[24], frame[25]]).into()),
+            _ => None,
+        }
+    }
+}
+
+/// A 6-byte hardware (MAC) address.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl core::fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            b[0], b[1], b[2], b[3], b[4], b[5]
+        )
+    }
+}
+
+impl core::fmt::Debug for MacAddress {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        let table = b"0123456789abcdef";
-
-        let mut out = [0u8; 12 + 6 - 1];
-        for i in 0..(12 + 6 - 1) {
-            let b = self.0[i / 3];
-            out[i] = match (i + 1) % 3 {
-                0 => ':' as u8,
-                1 => table[(b >> 4) as usize],
-                2 => table[(b & 0xf) as usize],
-                _ => '?' as u8,
+        core::fmt::Display::fmt(self, f)
+    }
+}
+
+impl core::str::FromStr for MacAddress {
+    type Err = ();
+
+    /// Parses a MAC address of the form `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut out = [0u8; 6];
+        let mut parts = s.split(&[':', '-'][..]);
+        for b in out.iter_mut() {
+            let part = parts.next().ok_or(())?;
+            if part.len() != 2 || !part.bytes().all(|c| c.is_ascii_hexdigit()) {
+                return Err(());
             }
+            *b = u8::from_str_radix(part, 16).map_err(|_| ())?;
         }
+        if parts.next().is_some() {
+            return Err(());
+        }
+        Ok(MacAddress(out))
+    }
+}
+
+impl From<[u8; 6]> for MacAddress {
+    fn from(orig: [u8; 6]) -> Self {
+        MacAddress(orig)
+    }
+}
+
+impl MacAddress {
+    /// Returns true if this is a group (multicast or broadcast) address.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// Returns the Ethernet multicast address an IPv4 multicast group maps to:
+    /// 01:00:5e followed by the low 23 bits of the address. Returns None if
+    /// `ip` is not a multicast address.
+    pub fn from_multicast_ipv4(ip: Ipv4Addr) -> Option<Self> {
+        if !ip.is_multicast() {
+            return None;
+        }
+        let o = ip.octets();
+        Some(MacAddress([0x01, 0x00, 0x5e, o[1] & 0x7f, o[2], o[3]]))
+    }
+}
+
+/// The machine-readable network name, which is the MAC address of the access point.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BSSID(pub [u8; 6]);
+
+impl BSSID {
+    /// Returns the MAC address of the access point.
+    pub fn mac(&self) -> MacAddress {
+        MacAddress(self.0)
+    }
+}
+
+impl core::fmt::Display for BSSID {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.mac(), f)
+    }
+}
+
+impl core::fmt::Debug for BSSID {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.mac(), f)
+    }
+}
+
+impl core::str::FromStr for BSSID {
+    type Err = ();
 
-        f.write_str(core::str::from_utf8(&out).unwrap())
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Ok(s.parse::<MacAddress>()?.into())
+    }
+}
+
+impl From<MacAddress> for BSSID {
+    fn from(orig: MacAddress) -> Self {
+        BSSID(orig.0)
+    }
+}
+
+impl From<BSSID> for MacAddress {
+    fn from(orig: BSSID) -> Self {
+        MacAddress(orig.0)
+    }
+}
+
+impl From<[u8; 6]> for BSSID {
+    fn from(orig: [u8; 6]) -> Self {
+        BSSID(orig)
     }
 }
 
@@ -210,6 +845,22 @@ pub struct SSID {
     value: [u8; 33],
 }
 
+impl SSID {
+    /// Returns the raw bytes of the name.
+    pub fn as_bytes(&self) -> &[u8] {
+        // Unused unsafe warning is erroneous: needed for safe_packed_borrows
+        #[allow(unused_unsafe)]
+        unsafe {
+            &self.value[..(self.len as usize).min(32)]
+        }
+    }
+
+    /// Returns true if the name is empty, as it is for hidden networks.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
 impl core::fmt::Debug for SSID {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         // Unused unsafe warning is erroneous: needed for safe_packed_borrows
@@ -244,3 +895,221 @@ pub struct IPInfo {
     pub netmask: Ipv4Addr,
     pub gateway: Ipv4Addr,
 }
+
+/// The version of the firmware on the wifi chip, as parsed from the string
+/// returned by `GetVersion`.
+///
+/// Versions are compared as in semver: build metadata is ignored, and a
+/// pre-release comes before the release with the same number.
+#[derive(Debug, Clone)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+    /// The pre-release suffix, such as `rc1`, if any.
+    pub pre_release: String<heapless::consts::U16>,
+    /// Build metadata following a `+`, such as a commit hash. Not used when
+    /// comparing versions.
+    pub metadata: String<heapless::consts::U16>,
+}
+
+impl FirmwareVersion {
+    pub fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre_release: String::new(),
+            metadata: String::new(),
+        }
+    }
+
+    /// Returns `Err::Unsupported` if the RPC needs a newer firmware version.
+    /// Pre-release builds of the minimum version are not new enough.
+    pub fn check<R: RPC>(&self, rpc: &R) -> Result<(), Err<R::Error>> {
+        match rpc.min_version() {
+            Some(min) if *self < min => Err(Err::Unsupported),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl PartialEq for FirmwareVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == core::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for FirmwareVersion {}
+
+impl PartialOrd for FirmwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Pre-release suffixes are compared by their dot-separated parts, with
+/// numeric parts compared as numbers.
+impl Ord for FirmwareVersion {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        use core::cmp::Ordering;
+
+        let numbers = (self.major, self.minor, self.patch);
+        match numbers.cmp(&(other.major, other.minor, other.patch)) {
+            Ordering::Equal => {}
+            ord => return ord,
+        }
+
+        match (self.pre_release.is_empty(), other.pre_release.is_empty()) {
+            (true, true) => return Ordering::Equal,
+            (true, false) => return Ordering::Greater,
+            (false, true) => return Ordering::Less,
+            (false, false) => {}
+        }
+
+        let mut ours = self.pre_release.split('.');
+        let mut theirs = other.pre_release.split('.');
+        loop {
+            let ord = match (ours.next(), theirs.next()) {
+                (None, None) => return Ordering::Equal,
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(a), Some(b)) => match (a.parse::<u32>(), b.parse::<u32>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => a.cmp(b),
+                },
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+    }
+}
+
+impl core::str::FromStr for FirmwareVersion {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let s = s.trim_matches(char::from(0)).trim();
+        let s = s.strip_prefix(&['v', 'V'][..]).unwrap_or(s);
+
+        // Split off the numeric part, leaving the suffix.
+        let end = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (numbers, suffix) = s.split_at(end);
+
+        let mut parts = numbers.trim_end_matches('.').split('.');
+        let major = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+        let minor = match parts.next() {
+            Some(p) => p.parse().map_err(|_| ())?,
+            None => 0,
+        };
+        let patch = match parts.next() {
+            Some(p) => p.parse().map_err(|_| ())?,
+            None => 0,
+        };
+        if parts.next().is_some() {
+            return Err(());
+        }
+
+        let (pre_release, metadata) = match suffix.find('+') {
+            Some(i) => (&suffix[..i], &suffix[i + 1..]),
+            None => (suffix, ""),
+        };
+
+        let mut out = Self::new(major, minor, patch);
+        for c in pre_release.trim_start_matches(&['-', '_'][..]).chars() {
+            if out.pre_release.push(c).is_err() {
+                break;
+            }
+        }
+        for c in metadata.chars() {
+            if out.metadata.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl core::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre_release.is_empty() {
+            write!(f, "-{}", self.pre_release)?;
+        }
+        if !self.metadata.is_empty() {
+            write!(f, "+{}", self.metadata)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn firmware_version_ordering() {
+        let v = |s: &str| s.parse::<FirmwareVersion>().unwrap();
+        assert!(v("1.2.3-rc1") < v("1.2.3"));
+        assert!(v("1.2.3-rc.2") < v("1.2.3-rc.10"));
+        assert!(v("1.2.3-1") < v("1.2.3-alpha"));
+        assert!(v("1.2.3") < v("1.2.4-rc1"));
+        assert_eq!(v("v1.2.3"), v("1.2.3"));
+        assert_eq!(v("1.2.3+g1234"), v("1.2.3"));
+        assert!(v("1.2.3-rc1+g1234") < v("1.2.3"));
+        assert_eq!(v("1.2.3-rc.01"), v("1.2.3-rc.1"));
+    }
+
+    #[test]
+    fn disconnect_reason_from_frame() {
+        let mut frame = [0u8; 26];
+        frame[0] = 12 << 4; // Deauthentication
+        frame[24] = 15;
+        assert_eq!(
+            DisconnectReason::from_frame(&frame),
+            Some(DisconnectReason::HandshakeTimeout)
+        );
+
+        frame[0] = 8 << 4; // Beacon
+        assert_eq!(DisconnectReason::from_frame(&frame), None);
+    }
+
+    #[test]
+    fn firmware_version_check() {
+        struct Newer;
+
+        impl RPC for Newer {
+            type ReturnValue = ();
+            type Error = ();
+
+            fn header(&self, seq: u32) -> Header {
+                Header {
+                    sequence: seq,
+                    msg_type: MsgType::Invocation,
+                    service: Service::Wifi,
+                    request: 0,
+                }
+            }
+
+            fn min_version(&self) -> Option<FirmwareVersion> {
+                Some(FirmwareVersion::new(2, 1, 0))
+            }
+
+            fn parse(&mut self, _data: &[u8]) -> Result<(), Err<()>> {
+                Ok(())
+            }
+        }
+
+        let v = |s: &str| s.parse::<FirmwareVersion>().unwrap();
+        let rpc = Newer;
+        assert_eq!(v("2.1.0").check(&rpc), Ok(()));
+        assert_eq!(v("2.1.0+g1234").check(&rpc), Ok(()));
+        assert_eq!(v("2.1.0-rc1").check(&rpc), Err(Err::Unsupported));
+        assert_eq!(v("2.0.9").check(&rpc), Err(Err::Unsupported));
+    }
+}
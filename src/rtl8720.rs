@@ -0,0 +1,603 @@
+use super::{
+    rpcs, Capabilities, Client, Err, Event, EventQueue, FirmwareVersion, FrameHeader, Header,
+    MsgLen, MsgType, OverflowPolicy, RPC,
+};
+use embedded_hal::{
+    blocking::delay::{DelayMs, DelayUs},
+    blocking::serial::Write,
+    digital::v2::OutputPin,
+    serial::Read,
+};
+use heapless::{
+    consts::{U1024, U8},
+    ArrayLength, Vec,
+};
+
+/// Interval between polls of the UART while waiting for data.
+const POLL_INTERVAL_US: u32 = 10;
+
+/// Time charged against the reply timeout for each byte received, roughly one
+/// byte at 115200 baud. This keeps a steady stream of callback events from
+/// holding a call open past its timeout.
+const BYTE_TIME_US: u32 = 87;
+
+/// Timing parameters used when resetting and talking to the RTL8720.
+#[derive(Debug, Copy, Clone)]
+pub struct Timing {
+    /// How long the enable line is held low to reset the chip.
+    pub reset_pulse_ms: u32,
+    /// How long the enable line is held low for a power cycle.
+    pub power_cycle_ms: u32,
+    /// How long to wait after reset before talking to the chip.
+    pub boot_ms: u32,
+    /// How long to keep trying to get a reply from the chip after it has booted.
+    pub sync_timeout_ms: u32,
+    /// How long to wait for the reply to an RPC.
+    pub reply_timeout_ms: u32,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            reset_pulse_ms: 50,
+            power_cycle_ms: 500,
+            boot_ms: 500,
+            sync_timeout_ms: 5000,
+            reply_timeout_ms: 1000,
+        }
+    }
+}
+
+/// Drives an RTL8720 wifi chip running the Seeed eRPC firmware, over a UART.
+///
+/// The reset pin is the chip enable line: driving it low holds the chip in
+/// reset. Callback events received while waiting on replies are buffered, and
+/// can be retrieved with `poll_event()`.
+pub struct Rtl8720<RST, D, S, N = U8>
+where
+    N: ArrayLength<Event>,
+{
+    reset: RST,
+    delay: D,
+    serial: S,
+    sequence: u32,
+    version: Option<FirmwareVersion>,
+    capabilities: Capabilities,
+    events: EventQueue<N>,
+    rx: Vec<u8, U1024>,
+    pub timing: Timing,
+}
+
+impl<RST, D, S, N> Rtl8720<RST, D, S, N>
+where
+    RST: OutputPin,
+    D: DelayMs<u32> + DelayUs<u32>,
+    S: Read<u8> + Write<u8>,
+    N: ArrayLength<Event>,
+{
+    pub fn new(reset: RST, delay: D, serial: S) -> Self {
+        Self {
+            reset,
+            delay,
+            serial,
+            sequence: 0,
+            version: None,
+            capabilities: Capabilities::all(),
+            events: EventQueue::new(OverflowPolicy::DropOldest),
+            rx: Vec::new(),
+            timing: Timing::default(),
+        }
+    }
+
+    /// Returns the reset pin, delay provider and UART.
+    pub fn free(self) -> (RST, D, S) {
+        (self.reset, self.delay, self.serial)
+    }
+
+    /// Resets the chip, and waits for it to boot and respond to RPCs.
+    pub fn reset(&mut self) -> Result<(), Err<()>> {
+        self.pulse(self.timing.reset_pulse_ms)?;
+        self.sync()
+    }
+
+    /// Holds the chip disabled for longer than a normal reset, then waits for
+    /// it to boot. Useful when the chip is no longer responding.
+    pub fn power_cycle(&mut self) -> Result<(), Err<()>> {
+        self.pulse(self.timing.power_cycle_ms)?;
+        self.sync()
+    }
+
+    fn pulse(&mut self, ms: u32) -> Result<(), Err<()>> {
+        self.reset.set_low().map_err(|_| Err::Unknown)?;
+        self.delay.delay_ms(ms);
+        self.reset.set_high().map_err(|_| Err::Unknown)?;
+        self.delay.delay_ms(self.timing.boot_ms);
+        Ok(())
+    }
+
+    /// Pings the chip until it replies, then reads its firmware version.
+    fn sync(&mut self) -> Result<(), Err<()>> {
+        self.version = None;
+        self.capabilities = Capabilities::all();
+
+        let mut waited = 0;
+        loop {
+            // Discard any boot messages or partial frames.
+            while self.serial.read().is_ok() {}
+
+            match self.call_timeout(&mut rpcs::Ack { value: 0x5a }, 100) {
+                Ok(()) => break,
+                Err(_) if waited < self.timing.sync_timeout_ms => waited += 100,
+                Err(_) => return Err(Err::Timeout),
+            }
+        }
+
+        let version = self
+            .call(&mut rpcs::GetVersion {})?
+            .parse()
+            .map_err(|_| Err::Unknown)?;
+        self.version = Some(version);
+        Ok(())
+    }
+
+    /// Determines which services the firmware implements, by probing each
+    /// in turn. Services without a known probe (LWIP, mbedTLS and BLE) cannot
+    /// be confirmed, so are left cleared; use `set_capabilities()` to enable
+    /// them once they are known to be present.
+    pub fn probe_capabilities(&mut self) -> Capabilities {
+        let mut caps = Capabilities::empty();
+        for &cap in &[
+            Capabilities::SYSTEM,
+            Capabilities::WIFI,
+            Capabilities::TCPIP,
+            Capabilities::LWIP,
+            Capabilities::MBEDTLS,
+            Capabilities::BLE,
+        ] {
+            if let Some(mut probe) = rpcs::Probe::for_capability(cap) {
+                if self
+                    .call_timeout(&mut probe, self.timing.reply_timeout_ms)
+                    .is_ok()
+                {
+                    caps.insert(cap);
+                }
+            }
+        }
+        self.capabilities = caps;
+        caps
+    }
+
+    /// Returns the firmware version read during the last reset.
+    pub fn version(&self) -> Option<&FirmwareVersion> {
+        self.version.as_ref()
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Sets the capabilities, such as those stored after a previous probe.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Returns the oldest callback event received during a call, if any.
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.poll_event()
+    }
+
+    /// Returns the queue of callback events received during calls.
+    pub fn events(&mut self) -> &mut EventQueue<N> {
+        &mut self.events
+    }
+
+    /// Issues an RPC, and waits for its reply. Returns `Err::Unsupported`
+    /// without sending anything if the firmware does not implement it.
+    pub fn call<R: RPC>(&mut self, rpc: &mut R) -> Result<R::ReturnValue, Err<R::Error>> {
+        if let Some(version) = &self.version {
+            version.check(rpc)?;
+        }
+        self.capabilities.check(rpc)?;
+        self.call_timeout(rpc, self.timing.reply_timeout_ms)
+    }
+
+    fn call_timeout<R: RPC>(
+        &mut self,
+        rpc: &mut R,
+        timeout_ms: u32,
+    ) -> Result<R::ReturnValue, Err<R::Error>> {
+        // The 8-byte header followed by the arguments.
+        let mut msg: Vec<u8, MsgLen> = Vec::new();
+        msg.extend_from_slice(&rpc.header(self.sequence).as_bytes())
+            .ok();
+        rpc.long_args(&mut msg);
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+
+        let frame = FrameHeader::new_from_msg(&msg);
+        self.serial
+            .bwrite_all(&frame.as_bytes())
+            .map_err(|_| Err::TXErr)?;
+        self.serial.bwrite_all(&msg).map_err(|_| Err::TXErr)?;
+        self.serial.bflush().map_err(|_| Err::TXErr)?;
+
+        let mut remaining_us = timeout_ms.saturating_mul(1000);
+        loop {
+            // A frame already being received is read to the end, but no new
+            // frame is started once the timeout has passed.
+            if remaining_us == 0 {
+                return Err(Err::Timeout);
+            }
+            let mut hdr = [0u8; 4];
+            for b in hdr.iter_mut() {
+                *b = self.read_byte(&mut remaining_us)?;
+            }
+            let (_, frame) = FrameHeader::parse::<_, ()>(&hdr[..])?;
+
+            self.rx = Vec::new();
+            let mut overrun = false;
+            for _ in 0..frame.msg_length {
+                let b = self.read_byte(&mut remaining_us)?;
+                overrun |= self.rx.push(b).is_err();
+            }
+            if overrun {
+                return Err(Err::ResponseOverrun);
+            }
+            frame.check_crc(&self.rx[..])?;
+
+            // Replies to earlier, timed-out calls are discarded.
+            let (_, hdr) = Header::parse::<_, ()>(&self.rx[..])?;
+            if hdr.msg_type == MsgType::Reply && hdr.sequence != sequence {
+                continue;
+            }
+
+            match rpc.parse(&self.rx) {
+                Err(Err::NotOurs) => {
+                    self.events.push_frame(&self.rx).ok();
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn read_byte<E>(&mut self, remaining_us: &mut u32) -> Result<u8, Err<E>> {
+        loop {
+            match self.serial.read() {
+                Ok(b) => {
+                    *remaining_us = remaining_us.saturating_sub(BYTE_TIME_US);
+                    return Ok(b);
+                }
+                Err(nb::Error::WouldBlock) => {
+                    if *remaining_us == 0 {
+                        return Err(Err::Timeout);
+                    }
+                    self.delay.delay_us(POLL_INTERVAL_US);
+                    *remaining_us = remaining_us.saturating_sub(POLL_INTERVAL_US);
+                }
+                Err(nb::Error::Other(_)) => return Err(Err::Unknown),
+            }
+        }
+    }
+}
+
+impl<RST, D, S, N> Client for Rtl8720<RST, D, S, N>
+where
+    RST: OutputPin,
+    D: DelayMs<u32> + DelayUs<u32>,
+    S: Read<u8> + Write<u8>,
+    N: ArrayLength<Event>,
+{
+    fn call<R: RPC>(&mut self, rpc: &mut R) -> Result<R::ReturnValue, Err<R::Error>> {
+        Rtl8720::call(self, rpc)
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.delay.delay_ms(ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{ids, rpcs};
+    use embedded_hal::{blocking::serial::write, serial};
+    use embedded_hal_mock::eh0::{
+        digital::{Mock as PinMock, State, Transaction},
+        MockError,
+    };
+    use std::{boxed::Box, cell::RefCell, collections::VecDeque, rc::Rc, vec, vec::Vec};
+
+    /// A pin or delay operation, in the order it was performed.
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    enum Op {
+        Low,
+        High,
+        DelayMs(u32),
+    }
+
+    type Log = Rc<RefCell<Vec<Op>>>;
+
+    /// Wraps a mock pin, recording each change in the shared log.
+    struct Pin {
+        mock: PinMock,
+        log: Log,
+    }
+
+    impl OutputPin for Pin {
+        type Error = MockError;
+
+        fn set_low(&mut self) -> Result<(), MockError> {
+            self.log.borrow_mut().push(Op::Low);
+            self.mock.set_low()
+        }
+
+        fn set_high(&mut self) -> Result<(), MockError> {
+            self.log.borrow_mut().push(Op::High);
+            self.mock.set_high()
+        }
+    }
+
+    /// Records millisecond delays in the shared log. Microsecond delays are
+    /// only used while polling the UART, so are not recorded.
+    struct Delay {
+        log: Log,
+    }
+
+    impl DelayMs<u32> for Delay {
+        fn delay_ms(&mut self, ms: u32) {
+            self.log.borrow_mut().push(Op::DelayMs(ms));
+        }
+    }
+
+    impl DelayUs<u32> for Delay {
+        fn delay_us(&mut self, _us: u32) {}
+    }
+
+    type Responder = Box<dyn FnMut(&Header, &[u8]) -> Vec<Vec<u8>>>;
+
+    /// A UART which passes each request to a closure when flushed, and queues
+    /// the messages it returns to be read back as frames.
+    struct Serial {
+        tx: Vec<u8>,
+        rx: VecDeque<u8>,
+        respond: Responder,
+    }
+
+    impl Serial {
+        fn new(respond: impl FnMut(&Header, &[u8]) -> Vec<Vec<u8>> + 'static) -> Self {
+            Self {
+                tx: Vec::new(),
+                rx: VecDeque::new(),
+                respond: Box::new(respond),
+            }
+        }
+    }
+
+    impl serial::Read<u8> for Serial {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            self.rx.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    impl serial::Write<u8> for Serial {
+        type Error = ();
+
+        fn write(&mut self, word: u8) -> nb::Result<(), ()> {
+            self.tx.push(word);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), ()> {
+            let (args, hdr) = Header::parse::<_, ()>(&self.tx[4..]).unwrap();
+            for msg in (self.respond)(&hdr, args) {
+                self.rx
+                    .extend(FrameHeader::new_from_msg(&msg).as_bytes().iter());
+                self.rx.extend(msg.iter());
+            }
+            self.tx.clear();
+            Ok(())
+        }
+    }
+
+    impl write::Default<u8> for Serial {}
+
+    fn message(msg_type: MsgType, request: &Header, sequence: u32, payload: &[u8]) -> Vec<u8> {
+        let hdr = Header {
+            sequence,
+            msg_type,
+            service: request.service,
+            request: request.request,
+        };
+        let mut out = hdr.as_bytes().to_vec();
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn reply(request: &Header, payload: &[u8]) -> Vec<u8> {
+        message(MsgType::Reply, request, request.sequence, payload)
+    }
+
+    /// Replies to the Ack and GetVersion requests issued when syncing.
+    fn sync_reply(request: &Header, args: &[u8]) -> Option<Vec<u8>> {
+        if request.service != ids::Service::System {
+            return None;
+        }
+        if request.request == u8::from(ids::SystemRequest::AckID) {
+            Some(reply(request, args))
+        } else {
+            Some(reply(request, b"\x05\x00\x00\x002.1.0"))
+        }
+    }
+
+    fn driver(pin: &[Transaction], serial: Serial) -> (Rtl8720<Pin, Delay, Serial>, Log) {
+        let log = Log::default();
+        let pin = Pin {
+            mock: PinMock::new(pin),
+            log: log.clone(),
+        };
+        let delay = Delay { log: log.clone() };
+        (Rtl8720::new(pin, delay, serial), log)
+    }
+
+    fn reset_pin() -> [Transaction; 2] {
+        [Transaction::set(State::Low), Transaction::set(State::High)]
+    }
+
+    #[test]
+    fn reset_pulse_order() {
+        let serial = Serial::new(|hdr, args| sync_reply(hdr, args).into_iter().collect());
+        let (mut rtl, log) = driver(&reset_pin(), serial);
+
+        assert_eq!(rtl.reset(), Ok(()));
+        assert_eq!(
+            &log.borrow()[..],
+            &[Op::Low, Op::DelayMs(50), Op::High, Op::DelayMs(500)]
+        );
+        assert_eq!(rtl.version(), Some(&FirmwareVersion::new(2, 1, 0)));
+
+        let (mut pin, _, _) = rtl.free();
+        pin.mock.done();
+    }
+
+    #[test]
+    fn power_cycle_uses_power_cycle_ms() {
+        let serial = Serial::new(|hdr, args| sync_reply(hdr, args).into_iter().collect());
+        let (mut rtl, log) = driver(&reset_pin(), serial);
+        rtl.timing.power_cycle_ms = 1234;
+
+        assert_eq!(rtl.power_cycle(), Ok(()));
+        assert_eq!(
+            &log.borrow()[..],
+            &[Op::Low, Op::DelayMs(1234), Op::High, Op::DelayMs(500)]
+        );
+
+        let (mut pin, _, _) = rtl.free();
+        pin.mock.done();
+    }
+
+    #[test]
+    fn sync_retries_ack() {
+        let acks = Rc::new(RefCell::new(0));
+        let counter = acks.clone();
+        let serial = Serial::new(move |hdr, args| {
+            if hdr.request == u8::from(ids::SystemRequest::AckID) {
+                *counter.borrow_mut() += 1;
+                if *counter.borrow() < 3 {
+                    return vec![];
+                }
+            }
+            sync_reply(hdr, args).into_iter().collect()
+        });
+        let (mut rtl, _) = driver(&reset_pin(), serial);
+
+        assert_eq!(rtl.reset(), Ok(()));
+        assert_eq!(*acks.borrow(), 3);
+
+        let (mut pin, _, _) = rtl.free();
+        pin.mock.done();
+    }
+
+    #[test]
+    fn sync_timeout() {
+        let acks = Rc::new(RefCell::new(0));
+        let counter = acks.clone();
+        let serial = Serial::new(move |_, _| {
+            *counter.borrow_mut() += 1;
+            vec![]
+        });
+        let (mut rtl, _) = driver(&reset_pin(), serial);
+        rtl.timing.sync_timeout_ms = 200;
+
+        assert_eq!(rtl.reset(), Err(Err::Timeout));
+        assert_eq!(*acks.borrow(), 3);
+        assert_eq!(rtl.version(), None);
+
+        let (mut pin, _, _) = rtl.free();
+        pin.mock.done();
+    }
+
+    #[test]
+    fn callbacks_queued_during_call() {
+        let serial = Serial::new(|hdr, args| {
+            if let Some(msg) = sync_reply(hdr, args) {
+                return vec![msg];
+            }
+            let event = Header {
+                sequence: 0,
+                msg_type: MsgType::Oneway,
+                service: ids::Service::WifiCallback,
+                request: ids::WifiCallbackRequest::Event.into(),
+            };
+            vec![
+                message(MsgType::Oneway, &event, 0, &[1, 2, 3]),
+                reply(hdr, &[1]),
+            ]
+        });
+        let (mut rtl, _) = driver(&reset_pin(), serial);
+        rtl.reset().unwrap();
+
+        assert_eq!(rtl.call(&mut rpcs::IsScanning {}), Ok(true));
+        let event = rtl.poll_event().unwrap();
+        assert_eq!(event.service, ids::Service::WifiCallback);
+        assert_eq!(&event.data[..], &[1, 2, 3]);
+        assert!(rtl.poll_event().is_none());
+
+        let (mut pin, _, _) = rtl.free();
+        pin.mock.done();
+    }
+
+    #[test]
+    fn stale_replies_discarded() {
+        let serial = Serial::new(|hdr, args| {
+            if let Some(msg) = sync_reply(hdr, args) {
+                return vec![msg];
+            }
+            // A late reply to an earlier call of the same RPC, then ours.
+            vec![
+                message(MsgType::Reply, hdr, hdr.sequence.wrapping_sub(1), &[1]),
+                reply(hdr, &[0]),
+            ]
+        });
+        let (mut rtl, _) = driver(&reset_pin(), serial);
+        rtl.reset().unwrap();
+
+        assert_eq!(rtl.call(&mut rpcs::IsScanning {}), Ok(false));
+        assert!(rtl.poll_event().is_none());
+
+        let (mut pin, _, _) = rtl.free();
+        pin.mock.done();
+    }
+
+    #[test]
+    fn callbacks_do_not_extend_timeout() {
+        let serial = Serial::new(|hdr, args| {
+            if let Some(msg) = sync_reply(hdr, args) {
+                return vec![msg];
+            }
+            // Events keep arriving, but the reply never does.
+            let event = Header {
+                sequence: 0,
+                msg_type: MsgType::Oneway,
+                service: ids::Service::WifiCallback,
+                request: ids::WifiCallbackRequest::Event.into(),
+            };
+            (0..1000)
+                .map(|_| message(MsgType::Oneway, &event, 0, &[1, 2, 3]))
+                .collect()
+        });
+        let (mut rtl, _) = driver(&reset_pin(), serial);
+        rtl.reset().unwrap();
+        rtl.timing.reply_timeout_ms = 100;
+
+        assert_eq!(rtl.call(&mut rpcs::IsScanning {}), Err(Err::Timeout));
+        assert!(rtl.poll_event().is_some());
+
+        let (mut pin, _, serial) = rtl.free();
+        assert!(!serial.rx.is_empty());
+        pin.mock.done();
+    }
+}
@@ -39,3 +39,179 @@ impl super::RPC for GetVersion {
         Ok(out)
     }
 }
+
+/// Sends a byte to the wifi chip, which echoes it back. Useful for checking the
+/// eRPC server is alive, or as a keepalive.
+pub struct Ack {
+    pub value: u8,
+}
+
+impl super::RPC for Ack {
+    type ReturnValue = ();
+    type Error = u8;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::System,
+            request: ids::SystemRequest::AckID.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.push(self.value).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::System
+            || hdr.request != ids::SystemRequest::AckID.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        // The echoed value is returned as the error if it does not match.
+        let (_, echo) = streaming::le_u8(data)?;
+        if echo != self.value {
+            Err(Err::RPCErr(echo))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Issues a harmless request to a service, to check whether it is implemented
+/// by the firmware. The firmware does not reply to requests for services or
+/// requests it does not implement, so the caller should treat a timeout as
+/// the service being absent.
+pub struct Probe {
+    pub service: ids::Service,
+    pub request: u8,
+    pub args: heapless::Vec<u8, U16>,
+}
+
+impl Probe {
+    /// Returns a probe for the given capability, or None if no harmless
+    /// request is known for the service behind it. Capabilities which cannot
+    /// be probed should be treated as absent until confirmed some other way.
+    pub fn for_capability(capability: super::Capabilities) -> Option<Self> {
+        use super::Capabilities;
+        let (service, request, args): (_, u8, &[u8]) = match capability {
+            Capabilities::SYSTEM => (
+                ids::Service::System,
+                ids::SystemRequest::VersionID.into(),
+                &[],
+            ),
+            Capabilities::WIFI => (ids::Service::Wifi, ids::WifiRequest::IsScanning.into(), &[]),
+            Capabilities::TCPIP => (
+                ids::Service::TCPIP,
+                ids::TCPIPRequest::GetIPInfo.into(),
+                &[0, 0, 0, 0], // Station interface
+            ),
+            _ => return None,
+        };
+
+        let mut out = Self {
+            service,
+            request,
+            args: heapless::Vec::new(),
+        };
+        out.args.extend_from_slice(args).ok();
+        Some(out)
+    }
+}
+
+impl super::RPC for Probe {
+    type ReturnValue = ();
+    type Error = ();
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: self.service,
+            request: self.request,
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.extend_from_slice(&self.args).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (_, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != self.service
+            || hdr.request != self.request
+        {
+            return Err(Err::NotOurs);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RPC;
+
+    fn reply(service: ids::Service, request: u8, payload: &[u8]) -> heapless::Vec<u8, U16> {
+        let hdr = codec::Header {
+            sequence: 0,
+            msg_type: ids::MsgType::Reply,
+            service,
+            request,
+        };
+        let mut out = heapless::Vec::new();
+        out.extend_from_slice(&hdr.as_bytes()).unwrap();
+        out.extend_from_slice(payload).unwrap();
+        out
+    }
+
+    #[test]
+    fn ack_args() {
+        let mut args = heapless::Vec::new();
+        Ack { value: 0x5a }.args(&mut args);
+        assert_eq!(&args[..], &[0x5a]);
+    }
+
+    #[test]
+    fn ack_parse_echo() {
+        let data = reply(
+            ids::Service::System,
+            ids::SystemRequest::AckID.into(),
+            &[0x5a],
+        );
+        assert_eq!(Ack { value: 0x5a }.parse(&data), Ok(()));
+    }
+
+    #[test]
+    fn ack_parse_mismatch() {
+        let data = reply(
+            ids::Service::System,
+            ids::SystemRequest::AckID.into(),
+            &[0x11],
+        );
+        assert_eq!(Ack { value: 0x5a }.parse(&data), Err(Err::RPCErr(0x11)));
+    }
+
+    #[test]
+    fn ack_parse_not_ours() {
+        let data = reply(
+            ids::Service::Wifi,
+            ids::SystemRequest::AckID.into(),
+            &[0x5a],
+        );
+        assert_eq!(Ack { value: 0x5a }.parse(&data), Err(Err::NotOurs));
+
+        let data = reply(
+            ids::Service::System,
+            ids::SystemRequest::VersionID.into(),
+            &[0x5a],
+        );
+        assert_eq!(Ack { value: 0x5a }.parse(&data), Err(Err::NotOurs));
+    }
+}
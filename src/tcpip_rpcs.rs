@@ -41,7 +41,7 @@ impl super::RPC for DHCPClientStop {
     type ReturnValue = i32;
     type Error = ();
 
-    fn args(&self, buff: &mut heapless::Vec<u8, heapless::consts::U64>) {
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
         let interface_id = self.interface as u32;
         buff.extend_from_slice(&interface_id.to_le_bytes()).ok();
     }
@@ -78,7 +78,7 @@ impl super::RPC for DHCPClientStart {
     type ReturnValue = i32;
     type Error = ();
 
-    fn args(&self, buff: &mut heapless::Vec<u8, heapless::consts::U64>) {
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
         let interface_id = self.interface as u32;
         buff.extend_from_slice(&interface_id.to_le_bytes()).ok();
     }
@@ -124,7 +124,7 @@ impl super::RPC for GetIPInfo {
         }
     }
 
-    fn args(&self, buff: &mut heapless::Vec<u8, heapless::consts::U64>) {
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
         let interface_id = self.interface as u32;
         buff.extend_from_slice(&interface_id.to_le_bytes()).ok();
     }
@@ -159,3 +159,90 @@ impl super::RPC for GetIPInfo {
         }
     }
 }
+
+/// Returns the MAC address of the given interface.
+pub struct GetMAC {
+    pub interface: super::L3Interface,
+}
+
+impl super::RPC for GetMAC {
+    type ReturnValue = super::MacAddress;
+    type Error = i32;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::TCPIP,
+            request: ids::TCPIPRequest::GetMAC.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        let interface_id = self.interface as u32;
+        buff.extend_from_slice(&interface_id.to_le_bytes()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::TCPIP
+            || hdr.request != ids::TCPIPRequest::GetMAC.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, mac) = take(6u8)(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result))
+        } else {
+            Ok(super::MacAddress([
+                mac[0], mac[1], mac[2], mac[3], mac[4], mac[5],
+            ]))
+        }
+    }
+}
+
+/// Sets the MAC address of the given interface.
+pub struct SetMAC {
+    pub interface: super::L3Interface,
+    pub mac: super::MacAddress,
+}
+
+impl super::RPC for SetMAC {
+    type ReturnValue = ();
+    type Error = i32;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::TCPIP,
+            request: ids::TCPIPRequest::SetMAC.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        let interface_id = self.interface as u32;
+        buff.extend_from_slice(&interface_id.to_le_bytes()).ok();
+        buff.extend_from_slice(&self.mac.0).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::TCPIP
+            || hdr.request != ids::TCPIPRequest::SetMAC.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -1,21 +1,15 @@
 #[allow(dead_code)]
 use super::{codec, ids, Err};
 use generic_array::{ArrayLength, GenericArray};
-use heapless::{
-    consts::{U18, U64},
-    String,
-};
-use nom::{
-    bytes::streaming::take, lib::std::ops::RangeFrom, lib::std::ops::RangeTo, number::streaming,
-    InputIter, InputLength, Slice,
-};
-
-/// Returns the mac address as a colon-separated hex string.
+use heapless::{consts::U64, String};
+use nom::{bytes::streaming::take, number::streaming, InputIter, InputLength};
+
+/// Returns the mac address of the wifi chip.
 pub struct GetMacAddress {}
 
 impl super::RPC for GetMacAddress {
-    type ReturnValue = String<U18>;
-    type Error = i32;
+    type ReturnValue = super::MacAddress;
+    type Error = super::WifiError;
 
     fn header(&self, seq: u32) -> codec::Header {
         codec::Header {
@@ -35,20 +29,17 @@ impl super::RPC for GetMacAddress {
             return Err(Err::NotOurs);
         }
 
-        if data.input_len() < 18 {
-            return Err(Err::RPCErr(-1));
-        }
-        let mut mac: String<U18> = String::new();
-        for b in data.slice(RangeTo { end: 17 }).iter_elements() {
-            mac.push(b as char).map_err(|_| Err::ResponseOverrun)?;
-        }
-
-        let (_, result) = streaming::le_u32(data.slice(RangeFrom { start: 18 }))?;
+        // The address is sent as a nul-terminated, colon-separated hex string.
+        let (data, text) = take(18usize)(data)?;
+        let (_, result) = streaming::le_i32(data)?;
         if result != 0 {
-            Err(Err::RPCErr(result as i32))
-        } else {
-            Ok(mac)
+            return Err(Err::RPCErr(result.into()));
         }
+
+        core::str::from_utf8(&text[..17])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or(Err::Parsing(nom::Err::Failure(())))
     }
 }
 
@@ -154,6 +145,213 @@ impl Default for ScanResult {
     }
 }
 
+impl ScanResult {
+    /// The length of a scan result in its wire format.
+    const LEN: usize = 62;
+
+    fn parse(data: &[u8]) -> nom::IResult<&[u8], Self, ()> {
+        use core::convert::TryInto;
+        let (d, ssid_len) = streaming::le_u8(data)?;
+        let (d, ssid_data) = take(33usize)(d)?;
+        let (d, bssid) = take(6usize)(d)?;
+        let (d, rssi) = streaming::le_i16(d)?;
+        let (d, bss_type) = streaming::le_u32(d)?;
+        let (d, security) = streaming::le_u32(d)?;
+        let (d, wps) = streaming::le_u32(d)?;
+        let (d, chan) = streaming::le_u32(d)?;
+        let (d, band) = streaming::le_u32(d)?;
+
+        Ok((
+            d,
+            ScanResult {
+                ssid: super::SSID {
+                    len: ssid_len,
+                    value: ssid_data.try_into().unwrap(),
+                },
+                bssid: super::BSSID(bssid.try_into().unwrap()),
+                rssi,
+                bss_type: bss_type.into(),
+                security: super::Security::from_bits_truncate(security),
+                wps: wps.into(),
+                chan,
+                band: band.into(),
+            },
+        ))
+    }
+}
+
+impl ScanResult {
+    /// Returns the signal quality as a percentage, scaled linearly between
+    /// -100dBm (0%) and -50dBm (100%).
+    pub fn quality(&self) -> u8 {
+        (2 * (self.rssi.clamp(-100, -50) + 100)) as u8
+    }
+
+    /// Returns true if the network does not broadcast its SSID.
+    pub fn is_hidden(&self) -> bool {
+        self.ssid.is_empty()
+    }
+}
+
+/// Criteria for choosing between scan results.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ScanFilter {
+    /// Drop networks with a weaker signal, in dBm.
+    pub min_rssi: Option<i16>,
+    /// Drop networks which do not support all of these security features.
+    /// `Security::empty()` keeps only open networks.
+    pub security: Option<super::Security>,
+    /// Keep networks which do not broadcast their SSID.
+    pub include_hidden: bool,
+}
+
+impl ScanFilter {
+    /// Security features which mean a network is not open. WPS alone does not.
+    const ENCRYPTED: super::Security = super::Security::from_bits_truncate(
+        super::Security::WEP_ENABLED.bits()
+            | super::Security::TKIP_ENABLED.bits()
+            | super::Security::AES_ENABLED.bits()
+            | super::Security::AES_CMAC_ENABLED.bits()
+            | super::Security::WPA_SECURITY.bits()
+            | super::Security::WPA2_SECURITY.bits()
+            | super::Security::WPA3_SECURITY.bits(),
+    );
+
+    pub fn matches(&self, result: &ScanResult) -> bool {
+        if !self.include_hidden && result.is_hidden() {
+            return false;
+        }
+        if let Some(min_rssi) = self.min_rssi {
+            if result.rssi < min_rssi {
+                return false;
+            }
+        }
+        if let Some(security) = self.security {
+            let ok = if security.is_empty() {
+                !result.security.intersects(Self::ENCRYPTED)
+            } else {
+                result.security.contains(security)
+            };
+            if !ok {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Helpers for working with a set of scan results, without allocating.
+pub trait ScanResultsExt {
+    /// Sorts the results, strongest signal first.
+    fn sort_by_signal(&mut self);
+
+    /// Sorts the results by SSID, strongest signal first within each SSID.
+    fn sort_by_ssid(&mut self);
+
+    /// Moves the results matching the filter to the front, preserving their
+    /// order, and returns how many matched.
+    fn filter_in_place(&mut self, filter: &ScanFilter) -> usize;
+
+    /// Moves the strongest result for each SSID to the front, preserving their
+    /// order, and returns how many there are. Merges access points which share
+    /// an SSID, such as on different bands. The weaker duplicates are moved to
+    /// the tail, in no particular order.
+    fn dedup_by_ssid(&mut self) -> usize;
+
+    /// Returns runs of results sharing an SSID. The results should be sorted
+    /// by SSID first.
+    fn group_by_ssid(&self) -> SSIDGroups<'_>;
+
+    /// Returns the strongest result for the given SSID.
+    fn best_for_ssid(&self, ssid: &[u8]) -> Option<&ScanResult>;
+
+    /// Returns the strongest result matching the filter.
+    fn best(&self, filter: &ScanFilter) -> Option<&ScanResult>;
+}
+
+impl ScanResultsExt for [ScanResult] {
+    fn sort_by_signal(&mut self) {
+        self.sort_unstable_by_key(|r| core::cmp::Reverse(r.rssi));
+    }
+
+    fn sort_by_ssid(&mut self) {
+        self.sort_unstable_by(|a, b| {
+            a.ssid
+                .as_bytes()
+                .cmp(b.ssid.as_bytes())
+                .then(b.rssi.cmp(&a.rssi))
+        });
+    }
+
+    fn filter_in_place(&mut self, filter: &ScanFilter) -> usize {
+        let mut kept = 0;
+        for i in 0..self.len() {
+            if filter.matches(&self[i]) {
+                self[kept..=i].rotate_right(1);
+                kept += 1;
+            }
+        }
+        kept
+    }
+
+    fn dedup_by_ssid(&mut self) -> usize {
+        let mut kept = 0;
+        for i in 0..self.len() {
+            // Hidden networks are never merged, as they may be unrelated.
+            let ssid = self[i].ssid;
+            match self[..kept]
+                .iter()
+                .position(|r| !ssid.is_empty() && r.ssid.as_bytes() == ssid.as_bytes())
+            {
+                Some(j) if self[j].rssi >= self[i].rssi => {}
+                Some(j) => self.swap(j, i),
+                None => {
+                    self[kept..=i].rotate_right(1);
+                    kept += 1;
+                }
+            }
+        }
+        kept
+    }
+
+    fn group_by_ssid(&self) -> SSIDGroups<'_> {
+        SSIDGroups { remaining: self }
+    }
+
+    fn best_for_ssid(&self, ssid: &[u8]) -> Option<&ScanResult> {
+        self.iter()
+            .filter(|r| r.ssid.as_bytes() == ssid)
+            .max_by_key(|r| r.rssi)
+    }
+
+    fn best(&self, filter: &ScanFilter) -> Option<&ScanResult> {
+        self.iter()
+            .filter(|r| filter.matches(r))
+            .max_by_key(|r| r.rssi)
+    }
+}
+
+/// Iterator over runs of scan results sharing an SSID.
+pub struct SSIDGroups<'a> {
+    remaining: &'a [ScanResult],
+}
+
+impl<'a> Iterator for SSIDGroups<'a> {
+    type Item = &'a [ScanResult];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = self.remaining.first()?;
+        let len = self
+            .remaining
+            .iter()
+            .position(|r| r.ssid.as_bytes() != first.ssid.as_bytes())
+            .unwrap_or(self.remaining.len());
+        let (group, rest) = self.remaining.split_at(len);
+        self.remaining = rest;
+        Some(group)
+    }
+}
+
 /// Returns N number of scan results. This RPC must only be called after starting a
 /// scan, and after IsScanning returns false.
 pub struct ScanGetAP<N: ArrayLength<ScanResult>> {
@@ -181,7 +379,7 @@ impl<N: ArrayLength<ScanResult>> super::RPC for ScanGetAP<N> {
         }
     }
 
-    fn args(&self, buff: &mut heapless::Vec<u8, heapless::consts::U64>) {
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
         let num = N::to_u16().to_le_bytes();
         buff.extend_from_slice(&num).ok();
     }
@@ -196,36 +394,14 @@ impl<N: ArrayLength<ScanResult>> super::RPC for ScanGetAP<N> {
         }
 
         let (mut data, l) = streaming::le_u32(data)?; // Binary len - returning 62 bytes per result
-        if l as usize != (62 * N::to_usize()) {
+        if l as usize != (ScanResult::LEN * N::to_usize()) {
             return Err(Err::ResponseOverrun);
         }
 
-        use core::convert::TryInto;
         let mut res = GenericArray::<ScanResult, N>::default();
-        for i in 0..N::to_usize() {
-            let (d, ssid_len) = streaming::le_u8(data)?;
-            let (d, ssid_data) = take(33usize)(d)?;
-            let (d, bssid) = take(6usize)(d)?;
-            let (d, rssi) = streaming::le_i16(d)?;
-            let (d, bss_type) = streaming::le_u32(d)?;
-            let (d, security) = streaming::le_u32(d)?;
-            let (d, wps) = streaming::le_u32(d)?;
-            let (d, chan) = streaming::le_u32(d)?;
-            let (d, band) = streaming::le_u32(d)?;
-
-            res[i] = ScanResult {
-                ssid: super::SSID {
-                    len: ssid_len,
-                    value: ssid_data.try_into().unwrap(),
-                },
-                bssid: super::BSSID(bssid.try_into().unwrap()),
-                rssi,
-                bss_type: bss_type.into(),
-                security: super::Security::from_bits_truncate(security),
-                wps: wps.into(),
-                chan,
-                band: band.into(),
-            };
+        for r in res.iter_mut() {
+            let (d, result) = ScanResult::parse(data)?;
+            *r = result;
             data = d;
         }
 
@@ -234,6 +410,71 @@ impl<N: ArrayLength<ScanResult>> super::RPC for ScanGetAP<N> {
     }
 }
 
+/// Fetches scan results into a caller-provided slice, requesting as many as
+/// fit in the slice or were found by the scan (`available`, as returned by
+/// `ScanGetNumAPs`), whichever is fewer. Returns the number of entries filled.
+/// This RPC must only be called after starting a scan, and after IsScanning
+/// returns false.
+pub struct ScanGetAPInto<'a> {
+    pub results: &'a mut [ScanResult],
+    pub available: u16,
+}
+
+impl ScanGetAPInto<'_> {
+    fn requested(&self) -> usize {
+        self.results.len().min(self.available as usize)
+    }
+}
+
+impl super::RPC for ScanGetAPInto<'_> {
+    type ReturnValue = usize;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::ScanGetAP.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        let num = self.requested() as u16;
+        buff.extend_from_slice(&num.to_le_bytes()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::ScanGetAP.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        // The firmware may return fewer results than requested.
+        let (mut data, l) = streaming::le_u32(data)?;
+        let count = l as usize / ScanResult::LEN;
+        if count * ScanResult::LEN != l as usize || count > self.requested() {
+            return Err(Err::ResponseOverrun);
+        }
+
+        for r in self.results[..count].iter_mut() {
+            let (d, result) = ScanResult::parse(data)?;
+            *r = result;
+            data = d;
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(count)
+        }
+    }
+}
+
 /// Returns the number of APs which were detected.
 pub struct ScanGetNumAPs {}
 
@@ -297,6 +538,68 @@ impl super::RPC for ScanStart {
     }
 }
 
+/// Restricts subsequent scans to the given channels. The restriction applies
+/// to the next scan only.
+///
+/// This is the only scan option the firmware offers: scans cannot target an
+/// SSID or be passive. The dwell time cannot be given exactly, only shortened
+/// using `fast`.
+pub struct SetScanChannels {
+    pub channels: heapless::Vec<u8, heapless::consts::U32>,
+    /// Spend less time on each channel, using Realtek's `PSCAN_FAST_SURVEY`.
+    pub fast: bool,
+}
+
+impl SetScanChannels {
+    fn encode<N: heapless::ArrayLength<u8>>(&self, buff: &mut heapless::Vec<u8, N>) {
+        buff.extend_from_slice(&(self.channels.len() as u32).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(&self.channels).ok();
+
+        // PSCAN_ENABLE, and optionally PSCAN_FAST_SURVEY.
+        buff.push(if self.fast { 0x03 } else { 0x01 }).ok();
+    }
+}
+
+impl super::RPC for SetScanChannels {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SetScanChannels.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        self.encode(buff);
+    }
+
+    fn long_args(&self, buff: &mut heapless::Vec<u8, super::MsgLen>) {
+        self.encode(buff);
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SetScanChannels.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 /// Turns on Wifi.
 pub struct WifiOn {
     pub mode: super::WifiMode,
@@ -306,7 +609,7 @@ impl super::RPC for WifiOn {
     type ReturnValue = i32;
     type Error = ();
 
-    fn args(&self, buff: &mut heapless::Vec<u8, heapless::consts::U64>) {
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
         let mode = self.mode as u32;
         buff.extend_from_slice(&mode.to_le_bytes()).ok();
     }
@@ -364,20 +667,143 @@ impl super::RPC for WifiOff {
     }
 }
 
+/// Disconnects from the current network, leaving the radio on.
+pub struct WifiDisconnect {}
+
+impl super::RPC for WifiDisconnect {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::Disconnect.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::Disconnect.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns true if the station interface is associated with an access point.
+pub struct IsConnectedToAP {}
+
+impl super::RPC for IsConnectedToAP {
+    type ReturnValue = bool;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::IsConnectedToAP.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::IsConnectedToAP.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        // The firmware returns RTW_SUCCESS when connected, and RTW_ERROR otherwise.
+        let (_, result) = streaming::le_i32(data)?;
+        match result {
+            0 => Ok(true),
+            -1 => Ok(false),
+            _ => Err(Err::RPCErr(result.into())),
+        }
+    }
+}
+
+/// Returns true if the given interface is up.
+pub struct IsUp {
+    pub interface: super::L3Interface,
+}
+
+impl super::RPC for IsUp {
+    type ReturnValue = bool;
+    type Error = super::WifiError;
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        let interface_id = self.interface as u32;
+        buff.extend_from_slice(&interface_id.to_le_bytes()).ok();
+    }
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::IsUp.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::IsUp.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        match result {
+            1 => Ok(true),
+            0 => Ok(false),
+            _ => Err(Err::RPCErr(result.into())),
+        }
+    }
+}
+
 /// Connects to the network with the provided properties.
 pub struct WifiConnect {
     pub ssid: String<U64>,
     pub password: String<U64>,
     pub security: super::Security,
     //key_id: u32,
+    /// Passed through as the firmware's `semaphore` argument. Only
+    /// `BLOCKING` is supported, which returns once the connection attempt
+    /// has completed.
     pub semaphore: u32,
 }
 
-impl super::RPC for WifiConnect {
-    type ReturnValue = i32;
-    type Error = ();
+impl WifiConnect {
+    /// Value of `semaphore` which waits for the connection attempt to complete.
+    pub const BLOCKING: u32 = 0;
+
+    /// Returns a connect request which blocks until the attempt completes.
+    pub fn new(ssid: String<U64>, password: String<U64>, security: super::Security) -> Self {
+        Self {
+            ssid,
+            password,
+            security,
+            semaphore: Self::BLOCKING,
+        }
+    }
 
-    fn args(&self, buff: &mut heapless::Vec<u8, U64>) {
+    fn encode<N: heapless::ArrayLength<u8>>(&self, buff: &mut heapless::Vec<u8, N>) {
         buff.extend_from_slice(&(self.ssid.len() as u32).to_le_bytes())
             .ok();
         buff.extend_from_slice(self.ssid.as_ref()).ok();
@@ -397,6 +823,19 @@ impl super::RPC for WifiConnect {
             .ok(); // key_id - always -1?
         buff.extend_from_slice(&(self.semaphore).to_le_bytes()).ok();
     }
+}
+
+impl super::RPC for WifiConnect {
+    type ReturnValue = ();
+    type Error = super::ConnectError;
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        self.encode(buff);
+    }
+
+    fn long_args(&self, buff: &mut heapless::Vec<u8, super::MsgLen>) {
+        self.encode(buff);
+    }
 
     fn header(&self, seq: u32) -> codec::Header {
         codec::Header {
@@ -416,7 +855,1849 @@ impl super::RPC for WifiConnect {
             return Err(Err::NotOurs);
         }
 
-        let (_, num) = streaming::le_i32(data)?;
-        Ok(num)
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Connects to a specific access point, identified by its BSSID. Useful when
+/// many access points share the same SSID.
+pub struct WifiConnectBSSID {
+    pub bssid: super::BSSID,
+    pub ssid: String<U64>,
+    pub password: String<U64>,
+    pub security: super::Security,
+    /// See `WifiConnect::semaphore`.
+    pub semaphore: u32,
+}
+
+impl WifiConnectBSSID {
+    /// Value of `semaphore` which waits for the connection attempt to complete.
+    pub const BLOCKING: u32 = 0;
+
+    /// Returns a connect request which blocks until the attempt completes.
+    pub fn new(
+        bssid: super::BSSID,
+        ssid: String<U64>,
+        password: String<U64>,
+        security: super::Security,
+    ) -> Self {
+        Self {
+            bssid,
+            ssid,
+            password,
+            security,
+            semaphore: Self::BLOCKING,
+        }
+    }
+
+    /// Encodes the arguments, or nothing at all if they would not fit. Long
+    /// SSIDs and passwords only fit with `long_args()`.
+    fn encode<N: heapless::ArrayLength<u8>>(&self, buff: &mut heapless::Vec<u8, N>) {
+        let bssid = self.bssid.0;
+        let password = if self.password.is_empty() {
+            0
+        } else {
+            4 + self.password.len()
+        };
+        let len = 4 + bssid.len() + 4 + self.ssid.len() + 1 + password + 4 + 4 + 4;
+        if buff.len() + len > buff.capacity() {
+            return;
+        }
+
+        buff.extend_from_slice(&(bssid.len() as u32).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(&bssid).ok();
+
+        buff.extend_from_slice(&(self.ssid.len() as u32).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(self.ssid.as_ref()).ok();
+
+        // Write the nullable flag (0 = NotNull, 1 = Null)
+        buff.push(if self.password.is_empty() { 1u8 } else { 0u8 })
+            .ok();
+        if !self.password.is_empty() {
+            buff.extend_from_slice(&(self.password.len() as u32).to_le_bytes())
+                .ok();
+            buff.extend_from_slice(self.password.as_ref()).ok();
+        }
+
+        buff.extend_from_slice(&(self.security.bits()).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(&(-1i32).to_le_bytes()).ok(); // key_id
+        buff.extend_from_slice(&(self.semaphore).to_le_bytes()).ok();
+    }
+}
+
+impl super::RPC for WifiConnectBSSID {
+    type ReturnValue = ();
+    type Error = super::ConnectError;
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        self.encode(buff);
+    }
+
+    fn long_args(&self, buff: &mut heapless::Vec<u8, super::MsgLen>) {
+        self.encode(buff);
+    }
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::ConnectBSSID.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::ConnectBSSID.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Starts an access point with the provided properties. An empty password
+/// starts an open network.
+pub struct WifiStartAP {
+    pub ssid: String<U64>,
+    pub password: String<U64>,
+    pub security: super::Security,
+    pub channel: i32,
+    /// If set, the SSID is not included in beacons.
+    pub hidden: bool,
+}
+
+impl WifiStartAP {
+    fn request(&self) -> u8 {
+        if self.hidden {
+            ids::WifiRequest::StartAPWithHiddenSSID.into()
+        } else {
+            ids::WifiRequest::StartAP.into()
+        }
+    }
+
+    /// Encodes the arguments, or nothing at all if they would not fit. Long
+    /// SSIDs and passwords only fit with `long_args()`.
+    fn encode<N: heapless::ArrayLength<u8>>(&self, buff: &mut heapless::Vec<u8, N>) {
+        let password = if self.password.is_empty() {
+            0
+        } else {
+            4 + self.password.len()
+        };
+        let len = 4 + self.ssid.len() + 1 + password + 4 + 4;
+        if buff.len() + len > buff.capacity() {
+            return;
+        }
+
+        buff.extend_from_slice(&(self.ssid.len() as u32).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(self.ssid.as_ref()).ok();
+
+        // Write the nullable flag (0 = NotNull, 1 = Null)
+        buff.push(if self.password.is_empty() { 1u8 } else { 0u8 })
+            .ok();
+        if !self.password.is_empty() {
+            buff.extend_from_slice(&(self.password.len() as u32).to_le_bytes())
+                .ok();
+            buff.extend_from_slice(self.password.as_ref()).ok();
+        }
+
+        buff.extend_from_slice(&(self.security.bits()).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(&(self.channel).to_le_bytes()).ok();
+    }
+}
+
+impl super::RPC for WifiStartAP {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        self.encode(buff);
+    }
+
+    fn long_args(&self, buff: &mut heapless::Vec<u8, super::MsgLen>) {
+        self.encode(buff);
+    }
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: self.request(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != self.request()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the MAC addresses of up to N stations associated with our access
+/// point. The firmware does not report the signal strength of each station.
+pub struct GetAssociatedClients<N: heapless::ArrayLength<super::MacAddress>> {
+    m: core::marker::PhantomData<N>,
+}
+
+impl<N: heapless::ArrayLength<super::MacAddress>> GetAssociatedClients<N> {
+    pub fn new() -> Self {
+        Self {
+            m: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<N: heapless::ArrayLength<super::MacAddress>> Default for GetAssociatedClients<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N: heapless::ArrayLength<super::MacAddress>> super::RPC for GetAssociatedClients<N> {
+    type ReturnValue = heapless::Vec<super::MacAddress, N>;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetAssociatedClientList.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        // A u32 count, followed by 6 bytes per client.
+        let len = (4 + 6 * N::to_usize()) as u16;
+        buff.extend_from_slice(&len.to_le_bytes()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetAssociatedClientList.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, l) = streaming::le_u32(data)?;
+        let (data, list) = take(l as usize)(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            return Err(Err::RPCErr(result.into()));
+        }
+
+        let (mut list, count) = streaming::le_u32(list)?;
+        let mut out = heapless::Vec::new();
+        for _ in 0..count {
+            let (l, mac) = take(6usize)(list)?;
+            out.push(super::MacAddress([
+                mac[0], mac[1], mac[2], mac[3], mac[4], mac[5],
+            ]))
+            .map_err(|_| Err::ResponseOverrun)?;
+            list = l;
+        }
+        Ok(out)
+    }
+}
+
+/// Returns the signal strength of the current connection, in dBm.
+pub struct GetRSSI {}
+
+impl super::RPC for GetRSSI {
+    type ReturnValue = i32;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetRSSI.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetRSSI.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, rssi) = streaming::le_i32(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(rssi)
+        }
+    }
+}
+
+/// Returns the BSSID of the access point we are connected to.
+pub struct GetAPBSSID {}
+
+impl super::RPC for GetAPBSSID {
+    type ReturnValue = super::BSSID;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetAPBSSID.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetAPBSSID.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, bssid) = take(6usize)(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(super::BSSID([
+                bssid[0], bssid[1], bssid[2], bssid[3], bssid[4], bssid[5],
+            ]))
+        }
+    }
+}
+
+/// Describes the configuration of a wifi interface.
+#[derive(Debug, Copy, Clone)]
+pub struct WifiSetting {
+    pub mode: super::WifiMode,
+    pub ssid: super::SSID,
+    pub channel: u8,
+    pub security: super::Security,
+}
+
+/// Returns the current settings of an interface, such as the network it is
+/// connected to (in station mode) or serving (in AP mode).
+pub struct GetSetting {
+    pub interface: super::L3Interface,
+}
+
+impl super::RPC for GetSetting {
+    type ReturnValue = WifiSetting;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetSetting.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        let ifname = self.interface.ifname();
+        buff.extend_from_slice(&(ifname.len() as u32).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(ifname.as_bytes()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetSetting.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, l) = streaming::le_u32(data)?;
+        let (data, setting) = take(l as usize)(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            return Err(Err::RPCErr(result.into()));
+        }
+
+        // Layout of rtw_wifi_setting_t, including padding.
+        use core::convert::TryInto;
+        let (d, mode) = streaming::le_u32(setting)?;
+        let (d, ssid) = take(33usize)(d)?;
+        let (d, channel) = streaming::le_u8(d)?;
+        let (d, _) = take(2usize)(d)?;
+        let (_, security) = streaming::le_u32(d)?;
+
+        Ok(WifiSetting {
+            mode: mode.into(),
+            ssid: super::SSID {
+                len: ssid.iter().position(|b| *b == 0).unwrap_or(32) as u8,
+                value: ssid.try_into().unwrap(),
+            },
+            channel,
+            security: super::Security::from_bits_truncate(security),
+        })
+    }
+}
+
+/// Sets the channel used by the radio, when in AP or promiscuous mode.
+pub struct SetChannel {
+    pub channel: i32,
+}
+
+impl super::RPC for SetChannel {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SetChannel.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.extend_from_slice(&self.channel.to_le_bytes()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SetChannel.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the channel the radio is currently using.
+pub struct GetChannel {}
+
+impl super::RPC for GetChannel {
+    type ReturnValue = i32;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetChannel.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetChannel.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, channel) = streaming::le_i32(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(channel)
+        }
+    }
+}
+
+/// Sets the regulatory domain, which determines the channels the radio may use.
+/// This is Realtek's `wifi_change_channel_plan()`; the firmware provides no
+/// way to read the current plan back.
+pub struct SetChannelPlan {
+    pub country: super::Country,
+}
+
+impl super::RPC for SetChannelPlan {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SetChannelPlan.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.push(self.country.channel_plan()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SetChannelPlan.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Sets the power saving mode of the radio. The DTIM interval of
+/// `PowerSave::Max` is set separately, using `SetLPSDTIM`; `PowerSave::apply()`
+/// issues both.
+pub struct SetPowerSave {
+    pub mode: super::PowerSave,
+}
+
+impl super::RPC for SetPowerSave {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SetPowerMode.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        // Inactive power save (IPS) is enabled with any power saving mode.
+        let ips_mode = if self.mode == super::PowerSave::None {
+            0u8
+        } else {
+            1u8
+        };
+        buff.push(ips_mode).ok();
+        buff.push(self.mode.lps_mode()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SetPowerMode.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Sets the number of DTIM intervals the radio sleeps for in `PowerSave::Max`.
+pub struct SetLPSDTIM {
+    pub dtim: u8,
+}
+
+impl super::RPC for SetLPSDTIM {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SetLPSDTIM.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.push(self.dtim).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SetLPSDTIM.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the number of DTIM intervals the radio sleeps for in `PowerSave::Max`.
+pub struct GetLPSDTIM {}
+
+impl super::RPC for GetLPSDTIM {
+    type ReturnValue = u8;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetLPSDTIM.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetLPSDTIM.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, dtim) = streaming::le_u8(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(dtim)
+        }
+    }
+}
+
+/// Sets how deeply the chip sleeps while in a power saving mode.
+pub struct SetLPSLevel {
+    pub level: super::LPSLevel,
+}
+
+impl super::RPC for SetLPSLevel {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SetLPSLevel.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.push(self.level as u8).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SetLPSLevel.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Configures the firmware to reconnect by itself after the connection is lost.
+pub struct ConfigAutoReconnect {
+    pub mode: super::AutoReconnect,
+    /// The number of attempts made, in `AutoReconnect::Finite` mode.
+    pub retries: u8,
+    /// The interval between attempts, in seconds.
+    pub interval: u16,
+}
+
+impl super::RPC for ConfigAutoReconnect {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::ConfigAutoReconnect.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.push(self.mode as u8).ok();
+        buff.push(self.retries).ok();
+        buff.extend_from_slice(&self.interval.to_le_bytes()).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::ConfigAutoReconnect.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Changes the auto-reconnect mode, keeping the configured retries and interval.
+pub struct SetAutoReconnect {
+    pub mode: super::AutoReconnect,
+}
+
+impl super::RPC for SetAutoReconnect {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SetAutoReconnect.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        buff.push(self.mode as u8).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SetAutoReconnect.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Returns the current auto-reconnect mode.
+pub struct GetAutoReconnect {}
+
+impl super::RPC for GetAutoReconnect {
+    type ReturnValue = super::AutoReconnect;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetAutoReconnect.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetAutoReconnect.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (data, mode) = streaming::le_u8(data)?;
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(mode.into())
+        }
+    }
+}
+
+/// Types of 802.11 management frames.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum MgmtSubtype {
+    AssocRequest = 0,
+    AssocResponse = 1,
+    ReassocRequest = 2,
+    ReassocResponse = 3,
+    ProbeRequest = 4,
+    ProbeResponse = 5,
+    Beacon = 8,
+    Disassoc = 10,
+    Auth = 11,
+    Deauth = 12,
+    Action = 13,
+}
+
+/// Builds the 24-byte header of an 802.11 management frame.
+#[derive(Debug, Copy, Clone)]
+pub struct MgmtHeader {
+    pub subtype: MgmtSubtype,
+    pub destination: super::MacAddress,
+    pub source: super::MacAddress,
+    pub bssid: super::MacAddress,
+    pub sequence: u16,
+}
+
+impl MgmtHeader {
+    pub const LEN: usize = 24;
+    const BROADCAST: super::MacAddress = super::MacAddress([0xff; 6]);
+
+    /// Returns the header of a broadcast probe request from `source`.
+    pub fn probe_request(source: super::MacAddress) -> Self {
+        Self {
+            subtype: MgmtSubtype::ProbeRequest,
+            destination: Self::BROADCAST,
+            source,
+            bssid: Self::BROADCAST,
+            sequence: 0,
+        }
+    }
+
+    /// Returns the header of a probe response sent by the access point `bssid`.
+    pub fn probe_response(bssid: super::MacAddress, destination: super::MacAddress) -> Self {
+        Self {
+            subtype: MgmtSubtype::ProbeResponse,
+            destination,
+            source: bssid,
+            bssid,
+            sequence: 0,
+        }
+    }
+
+    /// Returns the header of a beacon sent by the access point `bssid`.
+    pub fn beacon(bssid: super::MacAddress) -> Self {
+        Self {
+            subtype: MgmtSubtype::Beacon,
+            destination: Self::BROADCAST,
+            source: bssid,
+            bssid,
+            sequence: 0,
+        }
+    }
+
+    pub fn with_sequence(mut self, sequence: u16) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Encodes the header in its wire format.
+    pub fn as_bytes(&self) -> [u8; Self::LEN] {
+        let mut out = [0u8; Self::LEN];
+        out[0] = (self.subtype as u8) << 4; // Type 0 (management), version 0
+        out[4..10].copy_from_slice(&self.destination.0);
+        out[10..16].copy_from_slice(&self.source.0);
+        out[16..22].copy_from_slice(&self.bssid.0);
+        out[22..24].copy_from_slice(&(self.sequence << 4).to_le_bytes());
+        out
+    }
+}
+
+/// Transmits a raw 802.11 frame, such as a management frame beginning with a
+/// `MgmtHeader`. The frame can be at most `SendRawFrame::MAX_LEN` bytes.
+///
+/// The frame is too long for `RPC::args()` in all but trivial cases, so is
+/// only encoded by `RPC::long_args()`.
+pub struct SendRawFrame<'a> {
+    interface: super::L3Interface,
+    frame: &'a [u8],
+}
+
+impl<'a> SendRawFrame<'a> {
+    /// The largest frame which fits in the arguments of the RPC, alongside the
+    /// interface name and lengths.
+    pub const MAX_LEN: usize = 512 - 4 - 5 - 4 - 2;
+
+    /// Returns None if the frame is longer than `MAX_LEN`.
+    pub fn new(interface: super::L3Interface, frame: &'a [u8]) -> Option<Self> {
+        if frame.len() > Self::MAX_LEN {
+            return None;
+        }
+        Some(Self { interface, frame })
+    }
+
+    pub fn interface(&self) -> super::L3Interface {
+        self.interface
+    }
+
+    pub fn frame(&self) -> &'a [u8] {
+        self.frame
+    }
+
+    /// Encodes the arguments, or nothing at all if they would not fit, so a
+    /// truncated frame is never sent.
+    fn encode<N: heapless::ArrayLength<u8>>(&self, buff: &mut heapless::Vec<u8, N>) {
+        let ifname = self.interface.ifname();
+        let len = 4 + ifname.len() + 4 + self.frame.len() + 2;
+        if buff.len() + len > buff.capacity() {
+            return;
+        }
+
+        buff.extend_from_slice(&(ifname.len() as u32).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(ifname.as_bytes()).ok();
+
+        buff.extend_from_slice(&(self.frame.len() as u32).to_le_bytes())
+            .ok();
+        buff.extend_from_slice(self.frame).ok();
+        buff.extend_from_slice(&(self.frame.len() as u16).to_le_bytes())
+            .ok();
+    }
+}
+
+impl super::RPC for SendRawFrame<'_> {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::SendRawFrame.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        self.encode(buff);
+    }
+
+    fn long_args(&self, buff: &mut heapless::Vec<u8, super::MsgLen>) {
+        self.encode(buff);
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::SendRawFrame.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A vendor-specific information element, to be included in beacons and
+/// probe frames sent by the chip.
+#[derive(Debug, Clone)]
+pub struct InformationElement {
+    /// Organizationally unique identifier of the vendor.
+    pub oui: [u8; 3],
+    /// Vendor-defined type of the element.
+    pub ie_type: u8,
+    /// At most 251 bytes of vendor-defined data.
+    pub payload: heapless::Vec<u8, heapless::consts::U251>,
+    /// The frames the element is included in.
+    pub frames: super::IEFrames,
+}
+
+impl InformationElement {
+    const VENDOR_SPECIFIC: u8 = 221;
+
+    pub fn new(oui: [u8; 3], ie_type: u8, frames: super::IEFrames) -> Self {
+        Self {
+            oui,
+            ie_type,
+            payload: heapless::Vec::new(),
+            frames,
+        }
+    }
+
+    /// Sets the payload. Returns None if it is longer than 251 bytes.
+    ///
+    /// `RPC::args()` only fits payloads of up to 53 bytes for `AddCustomIE`,
+    /// and 49 for `UpdateCustomIE`. Longer payloads, up to the full 251 bytes,
+    /// must be sent using `RPC::long_args()`.
+    pub fn with_payload(mut self, payload: &[u8]) -> Option<Self> {
+        self.payload = heapless::Vec::new();
+        self.payload.extend_from_slice(payload).ok()?;
+        Some(self)
+    }
+
+    /// The number of bytes written by `encode()`.
+    fn encoded_len(&self) -> usize {
+        4 + 3 + 4 + self.payload.len()
+    }
+
+    /// Encodes the element as the firmware expects: the frame flags, followed
+    /// by the element itself. Nothing is written if it would not fit.
+    fn encode<N: heapless::ArrayLength<u8>>(&self, buff: &mut heapless::Vec<u8, N>) {
+        if buff.len() + self.encoded_len() > buff.capacity() {
+            return;
+        }
+
+        let len = 4 + self.payload.len();
+        buff.extend_from_slice(&(len as u32 + 3).to_le_bytes()).ok();
+        buff.push(self.frames.bits()).ok();
+        buff.push(Self::VENDOR_SPECIFIC).ok();
+        buff.push(len as u8).ok();
+        buff.extend_from_slice(&self.oui).ok();
+        buff.push(self.ie_type).ok();
+        buff.extend_from_slice(&self.payload).ok();
+    }
+}
+
+/// Adds a custom information element to the frames sent by the chip.
+pub struct AddCustomIE {
+    pub ie: InformationElement,
+}
+
+impl super::RPC for AddCustomIE {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::AddCustomIE.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        self.ie.encode(buff);
+    }
+
+    fn long_args(&self, buff: &mut heapless::Vec<u8, super::MsgLen>) {
+        self.ie.encode(buff);
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::AddCustomIE.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Replaces a previously added custom information element.
+pub struct UpdateCustomIE {
+    pub ie: InformationElement,
+    /// The position of the element to replace, starting from 1.
+    pub index: i32,
+}
+
+impl UpdateCustomIE {
+    /// Encodes the arguments, or nothing at all if they would not fit.
+    fn encode<N: heapless::ArrayLength<u8>>(&self, buff: &mut heapless::Vec<u8, N>) {
+        if buff.len() + self.ie.encoded_len() + 4 > buff.capacity() {
+            return;
+        }
+
+        self.ie.encode(buff);
+        buff.extend_from_slice(&self.index.to_le_bytes()).ok();
+    }
+}
+
+impl super::RPC for UpdateCustomIE {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::UpdateCustomIE.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        self.encode(buff);
+    }
+
+    fn long_args(&self, buff: &mut heapless::Vec<u8, super::MsgLen>) {
+        self.encode(buff);
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::UpdateCustomIE.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Removes all custom information elements.
+pub struct DelCustomIE {}
+
+impl super::RPC for DelCustomIE {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::DelCustomIE.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::DelCustomIE.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ScanState {
+    Idle,
+    Scanning { waited_ms: u32 },
+    Fetching,
+    Yielding,
+    Done,
+}
+
+/// Runs a complete scan: starting it, waiting for it to finish, and fetching
+/// the results. Results are yielded one at a time.
+///
+/// `ScanGetAP` takes only a count and no offset, so the results are fetched
+/// with a single call. At most N results, and never more than
+/// `Scanner::MAX_RESULTS`, are returned; any further networks found by the
+/// scan are dropped. Each result is 62 bytes on the wire, and the limit keeps
+/// the reply within the 1024-byte receive buffer of `Rtl8720`.
+pub struct Scanner<N: ArrayLength<ScanResult> = heapless::consts::U8> {
+    /// Restricts the scan to these channels, using `SetScanChannels`. If
+    /// None, all channels are scanned.
+    pub channels: Option<heapless::Vec<u8, heapless::consts::U32>>,
+    /// Spend less time on each channel. See `SetScanChannels::fast`; only
+    /// used when `channels` is set.
+    pub fast: bool,
+    /// How long to wait between checks that the scan has finished.
+    pub poll_interval_ms: u32,
+    /// How long to wait for the scan to finish.
+    pub timeout_ms: u32,
+    state: ScanState,
+    results: GenericArray<ScanResult, N>,
+    len: usize,
+    pos: usize,
+    total: u16,
+}
+
+impl<N: ArrayLength<ScanResult>> Default for Scanner<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N: ArrayLength<ScanResult>> Scanner<N> {
+    /// The most results fetched from one scan.
+    pub const MAX_RESULTS: usize = 16;
+
+    pub fn new() -> Self {
+        Self {
+            channels: None,
+            fast: false,
+            poll_interval_ms: 100,
+            timeout_ms: 10_000,
+            state: ScanState::Idle,
+            results: GenericArray::default(),
+            len: 0,
+            pos: 0,
+            total: 0,
+        }
+    }
+
+    /// Returns the scanner to its initial state, so the next call to `next()`
+    /// starts a new scan. The channels, `fast` and timings are kept.
+    pub fn reset(&mut self) {
+        self.state = ScanState::Idle;
+        self.len = 0;
+        self.pos = 0;
+        self.total = 0;
+    }
+
+    /// Returns the number of networks found by the scan, which may be more
+    /// than the number of results returned. Zero until the scan has finished.
+    pub fn found(&self) -> u16 {
+        self.total
+    }
+
+    /// Returns the next scan result, performing RPCs as needed. The first call
+    /// starts the scan. Returns None once all results have been returned.
+    pub fn next<C: super::Client>(
+        &mut self,
+        client: &mut C,
+    ) -> Option<Result<ScanResult, Err<super::WifiError>>> {
+        let generic = |_| super::WifiError::Generic;
+        loop {
+            match self.state {
+                ScanState::Idle => {
+                    let restricted = match &self.channels {
+                        Some(channels) => client.call(&mut SetScanChannels {
+                            channels: channels.clone(),
+                            fast: self.fast,
+                        }),
+                        None => Ok(()),
+                    };
+                    let started = restricted.and_then(|_| match client.call(&mut ScanStart {}) {
+                        Ok(0) => Ok(()),
+                        Ok(result) => Err(Err::RPCErr(result.into())),
+                        Err(e) => Err(e.map_rpc(generic)),
+                    });
+                    if let Err(e) = started {
+                        self.state = ScanState::Done;
+                        return Some(Err(e));
+                    }
+                    self.state = ScanState::Scanning { waited_ms: 0 };
+                }
+
+                ScanState::Scanning { waited_ms } => match client.call(&mut IsScanning {}) {
+                    Ok(true) if waited_ms >= self.timeout_ms => {
+                        self.state = ScanState::Done;
+                        return Some(Err(Err::Timeout));
+                    }
+                    Ok(true) => {
+                        client.delay_ms(self.poll_interval_ms);
+                        self.state = ScanState::Scanning {
+                            waited_ms: waited_ms + self.poll_interval_ms,
+                        };
+                    }
+                    Ok(false) => match client.call(&mut ScanGetNumAPs {}) {
+                        Ok(total) => {
+                            self.total = total;
+                            self.state = ScanState::Fetching;
+                        }
+                        Err(e) => {
+                            self.state = ScanState::Done;
+                            return Some(Err(e.map_rpc(generic)));
+                        }
+                    },
+                    Err(e) => {
+                        self.state = ScanState::Done;
+                        return Some(Err(e.map_rpc(generic)));
+                    }
+                },
+
+                ScanState::Fetching => {
+                    if self.total == 0 {
+                        self.state = ScanState::Done;
+                        continue;
+                    }
+
+                    let max = N::to_usize().min(Self::MAX_RESULTS);
+                    let mut rpc = ScanGetAPInto {
+                        results: &mut self.results[..max],
+                        available: self.total,
+                    };
+                    match client.call(&mut rpc) {
+                        Ok(n) => {
+                            self.len = n;
+                            self.pos = 0;
+                            self.state = ScanState::Yielding;
+                        }
+                        Err(e) => {
+                            self.state = ScanState::Done;
+                            return Some(Err(e));
+                        }
+                    }
+                }
+
+                ScanState::Yielding => {
+                    if self.pos >= self.len {
+                        self.state = ScanState::Done;
+                        continue;
+                    }
+                    self.pos += 1;
+                    return Some(Ok(self.results[self.pos - 1]));
+                }
+
+                ScanState::Done => return None,
+            }
+        }
+    }
+
+    /// Returns an iterator over the scan results.
+    pub fn results<'a, C: super::Client>(&'a mut self, client: &'a mut C) -> ScanResults<'a, C, N> {
+        ScanResults {
+            scanner: self,
+            client,
+        }
+    }
+
+    /// Calls `f` with each scan result, stopping at the first error.
+    pub fn for_each<C: super::Client>(
+        &mut self,
+        client: &mut C,
+        mut f: impl FnMut(&ScanResult),
+    ) -> Result<(), Err<super::WifiError>> {
+        while let Some(result) = self.next(client) {
+            f(&result?);
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the results of a `Scanner`.
+pub struct ScanResults<'a, C, N: ArrayLength<ScanResult>> {
+    scanner: &'a mut Scanner<N>,
+    client: &'a mut C,
+}
+
+impl<C: super::Client, N: ArrayLength<ScanResult>> Iterator for ScanResults<'_, C, N> {
+    type Item = Result<ScanResult, Err<super::WifiError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.scanner.next(self.client)
+    }
+}
+
+/// Returns the reason the last connection attempt failed.
+pub struct GetLastError {}
+
+impl super::RPC for GetLastError {
+    type ReturnValue = super::LastError;
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::GetLastError.into(),
+        }
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::GetLastError.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        Ok(result.into())
+    }
+}
+
+/// Adds a multicast MAC address to the receive filter, so frames sent to the
+/// group are delivered.
+pub struct RegisterMulticast {
+    mac: super::MacAddress,
+}
+
+impl RegisterMulticast {
+    /// Returns None if `mac` is not a multicast address.
+    pub fn new(mac: super::MacAddress) -> Option<Self> {
+        if !mac.is_multicast() {
+            return None;
+        }
+        Some(Self { mac })
+    }
+
+    /// Registers the MAC address an IPv4 multicast group maps to. Returns None if
+    /// `ip` is not a multicast address.
+    pub fn from_ipv4(ip: no_std_net::Ipv4Addr) -> Option<Self> {
+        super::MacAddress::from_multicast_ipv4(ip).map(|mac| Self { mac })
+    }
+
+    pub fn mac(&self) -> super::MacAddress {
+        self.mac
+    }
+}
+
+impl super::RPC for RegisterMulticast {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::RegisterMulticastAddress.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        // A fixed-size uint8[6], so no length is sent.
+        buff.extend_from_slice(&self.mac.0).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::RegisterMulticastAddress.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Removes a multicast MAC address from the receive filter.
+pub struct UnregisterMulticast {
+    mac: super::MacAddress,
+}
+
+impl UnregisterMulticast {
+    /// Returns None if `mac` is not a multicast address.
+    pub fn new(mac: super::MacAddress) -> Option<Self> {
+        if !mac.is_multicast() {
+            return None;
+        }
+        Some(Self { mac })
+    }
+
+    /// Unregisters the MAC address an IPv4 multicast group maps to. Returns None if
+    /// `ip` is not a multicast address.
+    pub fn from_ipv4(ip: no_std_net::Ipv4Addr) -> Option<Self> {
+        super::MacAddress::from_multicast_ipv4(ip).map(|mac| Self { mac })
+    }
+
+    pub fn mac(&self) -> super::MacAddress {
+        self.mac
+    }
+}
+
+impl super::RPC for UnregisterMulticast {
+    type ReturnValue = ();
+    type Error = super::WifiError;
+
+    fn header(&self, seq: u32) -> codec::Header {
+        codec::Header {
+            sequence: seq,
+            msg_type: ids::MsgType::Invocation,
+            service: ids::Service::Wifi,
+            request: ids::WifiRequest::UnregisterMulticastAddress.into(),
+        }
+    }
+
+    fn args(&self, buff: &mut heapless::Vec<u8, super::ArgsLen>) {
+        // A fixed-size uint8[6], so no length is sent.
+        buff.extend_from_slice(&self.mac.0).ok();
+    }
+
+    fn parse(&mut self, data: &[u8]) -> Result<Self::ReturnValue, Err<Self::Error>> {
+        let (data, hdr) = codec::Header::parse(data)?;
+        if hdr.msg_type != ids::MsgType::Reply
+            || hdr.service != ids::Service::Wifi
+            || hdr.request != ids::WifiRequest::UnregisterMulticastAddress.into()
+        {
+            return Err(Err::NotOurs);
+        }
+
+        let (_, result) = streaming::le_i32(data)?;
+        if result != 0 {
+            Err(Err::RPCErr(result.into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::{Security, RPC};
+    use std::{vec, vec::Vec};
+
+    fn result(ssid: &str, rssi: i16, security: Security) -> ScanResult {
+        let mut out = ScanResult {
+            rssi,
+            security,
+            ..ScanResult::default()
+        };
+        out.ssid.len = ssid.len() as u8;
+        out.ssid.value[..ssid.len()].copy_from_slice(ssid.as_bytes());
+        out
+    }
+
+    #[test]
+    fn dedup_by_ssid_keeps_all_results() {
+        let mut results = [
+            result("a", -70, Security::empty()),
+            result("b", -60, Security::empty()),
+            result("a", -50, Security::empty()),
+        ];
+        assert_eq!(results.dedup_by_ssid(), 2);
+        assert_eq!(
+            (results[0].ssid.as_bytes(), results[0].rssi),
+            (&b"a"[..], -50)
+        );
+        assert_eq!(
+            (results[1].ssid.as_bytes(), results[1].rssi),
+            (&b"b"[..], -60)
+        );
+        assert_eq!(
+            (results[2].ssid.as_bytes(), results[2].rssi),
+            (&b"a"[..], -70)
+        );
+    }
+
+    #[test]
+    fn filter_open_networks() {
+        let filter = ScanFilter {
+            security: Some(Security::empty()),
+            ..ScanFilter::default()
+        };
+        assert!(filter.matches(&result("open", -50, Security::empty())));
+        assert!(filter.matches(&result("wps", -50, Security::WPS_OPEN)));
+        assert!(!filter.matches(&result("wpa2", -50, Security::WPA2_AES_PSK)));
+
+        let filter = ScanFilter {
+            security: Some(Security::WPA2_SECURITY),
+            ..ScanFilter::default()
+        };
+        assert!(!filter.matches(&result("open", -50, Security::empty())));
+        assert!(filter.matches(&result("wpa2", -50, Security::WPA2_AES_PSK)));
+    }
+
+    #[test]
+    fn multicast_registration() {
+        let ip = no_std_net::Ipv4Addr::new(224, 0, 0, 251);
+        let rpc = RegisterMulticast::from_ipv4(ip).unwrap();
+        assert_eq!(rpc.mac(), crate::MacAddress([0x01, 0x00, 0x5e, 0, 0, 0xfb]));
+
+        let mut args = heapless::Vec::new();
+        rpc.args(&mut args);
+        assert_eq!(&args[..], &[0x01, 0x00, 0x5e, 0, 0, 0xfb]);
+
+        let ip = no_std_net::Ipv4Addr::new(239, 255, 255, 250);
+        let rpc = UnregisterMulticast::from_ipv4(ip).unwrap();
+        assert_eq!(
+            rpc.mac(),
+            crate::MacAddress([0x01, 0x00, 0x5e, 0x7f, 0xff, 0xfa])
+        );
+
+        assert!(RegisterMulticast::from_ipv4(no_std_net::Ipv4Addr::new(10, 0, 0, 1)).is_none());
+        assert!(RegisterMulticast::new(crate::MacAddress([0x00, 0, 0x5e, 0, 0, 1])).is_none());
+        assert!(UnregisterMulticast::new(crate::MacAddress([0x33, 0x33, 0, 0, 0, 1])).is_some());
+    }
+
+    #[test]
+    fn send_raw_frame_rejects_oversize() {
+        let frame = [0u8; SendRawFrame::MAX_LEN + 1];
+        assert!(SendRawFrame::new(crate::L3Interface::Station, &frame).is_none());
+        assert!(SendRawFrame::new(crate::L3Interface::Station, &frame[1..]).is_some());
+    }
+
+    #[test]
+    fn send_raw_frame_long_args() {
+        let frame = [0xaau8; SendRawFrame::MAX_LEN];
+        let rpc = SendRawFrame::new(crate::L3Interface::Station, &frame).unwrap();
+
+        let mut msg = heapless::Vec::new();
+        msg.extend_from_slice(&rpc.header(0).as_bytes()).unwrap();
+        rpc.long_args(&mut msg);
+        assert_eq!(msg.len(), msg.capacity());
+        assert_eq!(&msg[8..17], b"\x05\x00\x00\x00wlan0");
+
+        // Too long for args(), so nothing is encoded rather than a partial frame.
+        let mut args = heapless::Vec::new();
+        rpc.args(&mut args);
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn connect_bssid_encodes_all_or_nothing() {
+        let mut ssid = String::new();
+        let mut password = String::new();
+        for _ in 0..32 {
+            ssid.push('s').unwrap();
+            password.push('p').unwrap();
+        }
+        let rpc = WifiConnectBSSID::new(
+            crate::BSSID([1, 2, 3, 4, 5, 6]),
+            ssid,
+            password,
+            Security::WPA2_AES_PSK,
+        );
+
+        let mut msg = heapless::Vec::new();
+        rpc.long_args(&mut msg);
+        assert_eq!(msg.len(), 4 + 6 + 4 + 32 + 1 + 4 + 32 + 12);
+
+        let mut args = heapless::Vec::new();
+        rpc.args(&mut args);
+        assert!(args.is_empty());
+    }
+
+    #[test]
+    fn custom_ie_encodes_all_or_nothing() {
+        let ie = InformationElement::new([1, 2, 3], 4, crate::IEFrames::BEACON)
+            .with_payload(&[0xaa; 50])
+            .unwrap();
+
+        let mut args = heapless::Vec::new();
+        AddCustomIE { ie: ie.clone() }.args(&mut args);
+        assert_eq!(args.len(), 4 + 3 + 4 + 50);
+        assert_eq!(&args[..11], &[57, 0, 0, 0, 4, 221, 54, 1, 2, 3, 4]);
+
+        // The index no longer fits, so nothing is written, not even the IE.
+        let rpc = UpdateCustomIE { ie, index: 1 };
+        let mut args = heapless::Vec::new();
+        rpc.args(&mut args);
+        assert!(args.is_empty());
+
+        let mut msg = heapless::Vec::new();
+        rpc.long_args(&mut msg);
+        assert_eq!(msg.len(), 4 + 3 + 4 + 50 + 4);
+        assert_eq!(&msg[61..], &1i32.to_le_bytes());
+    }
+
+    /// Answers the scan RPCs as the firmware would, for a scan which finishes
+    /// after `polls` checks and finds `found` networks.
+    struct ScanClient {
+        polls: u32,
+        found: u16,
+        requests: Vec<(u8, Vec<u8>)>,
+        delayed_ms: u32,
+    }
+
+    impl ScanClient {
+        fn new(polls: u32, found: u16) -> Self {
+            Self {
+                polls,
+                found,
+                requests: Vec::new(),
+                delayed_ms: 0,
+            }
+        }
+
+        fn requests(&self) -> Vec<ids::WifiRequest> {
+            use ids::WifiRequest::*;
+            self.requests
+                .iter()
+                .map(|(r, _)| {
+                    *[
+                        SetScanChannels,
+                        ScanStart,
+                        IsScanning,
+                        ScanGetNumAPs,
+                        ScanGetAP,
+                    ]
+                    .iter()
+                    .find(|id| u8::from(**id) == *r)
+                    .unwrap()
+                })
+                .collect()
+        }
+    }
+
+    impl crate::Client for ScanClient {
+        fn call<R: RPC>(&mut self, rpc: &mut R) -> Result<R::ReturnValue, Err<R::Error>> {
+            let hdr = rpc.header(0);
+            let mut args = heapless::Vec::new();
+            rpc.long_args(&mut args);
+            self.requests.push((hdr.request, args.to_vec()));
+
+            let mut payload = Vec::new();
+            match hdr.request {
+                r if r == ids::WifiRequest::IsScanning.into() => {
+                    let scanning = self.polls > 0;
+                    self.polls = self.polls.saturating_sub(1);
+                    payload.extend_from_slice(&(scanning as i32).to_le_bytes());
+                }
+                r if r == ids::WifiRequest::ScanGetNumAPs.into() => {
+                    payload.extend_from_slice(&self.found.to_le_bytes());
+                }
+                r if r == ids::WifiRequest::ScanGetAP.into() => {
+                    let n = u16::from_le_bytes([args[0], args[1]]).min(self.found);
+                    payload.extend_from_slice(&(n as u32 * 62).to_le_bytes());
+                    for i in 0..n {
+                        payload.push(1);
+                        payload.push(b'a' + i as u8);
+                        payload.extend_from_slice(&[0; 32 + 6]);
+                        payload.extend_from_slice(&(-50i16).to_le_bytes());
+                        payload.extend_from_slice(&[0; 20]);
+                    }
+                    payload.extend_from_slice(&0i32.to_le_bytes());
+                }
+                _ => payload.extend_from_slice(&0i32.to_le_bytes()),
+            }
+
+            let reply = codec::Header {
+                msg_type: ids::MsgType::Reply,
+                ..hdr
+            };
+            let mut data = reply.as_bytes().to_vec();
+            data.extend_from_slice(&payload);
+            rpc.parse(&data)
+        }
+
+        fn delay_ms(&mut self, ms: u32) {
+            self.delayed_ms += ms;
+        }
+    }
+
+    #[test]
+    fn scanner_runs_scan() {
+        use ids::WifiRequest::*;
+
+        let mut client = ScanClient::new(2, 3);
+        let mut scanner = Scanner::<heapless::consts::U8>::new();
+        let mut channels = heapless::Vec::new();
+        channels.extend_from_slice(&[1, 6, 11]).unwrap();
+        scanner.channels = Some(channels);
+        scanner.fast = true;
+
+        let ssids: Vec<u8> = scanner
+            .results(&mut client)
+            .map(|r| r.unwrap().ssid.as_bytes()[0])
+            .collect();
+        assert_eq!(ssids, b"abc");
+        assert_eq!(scanner.found(), 3);
+        assert_eq!(client.delayed_ms, 200);
+        assert_eq!(
+            client.requests(),
+            vec![
+                SetScanChannels,
+                ScanStart,
+                IsScanning,
+                IsScanning,
+                IsScanning,
+                ScanGetNumAPs,
+                ScanGetAP
+            ]
+        );
+        assert_eq!(client.requests[0].1, [3, 0, 0, 0, 1, 6, 11, 0x03]);
+        assert!(scanner.next(&mut client).is_none());
+    }
+
+    #[test]
+    fn scanner_fetches_at_most_max_results() {
+        let mut client = ScanClient::new(0, 40);
+        let mut scanner = Scanner::<heapless::consts::U32>::new();
+
+        assert_eq!(scanner.results(&mut client).count(), 16);
+        assert_eq!(scanner.found(), 40);
+        let fetches: Vec<_> = client
+            .requests
+            .iter()
+            .filter(|(r, _)| *r == ids::WifiRequest::ScanGetAP.into())
+            .collect();
+        assert_eq!(fetches.len(), 1);
+        assert_eq!(fetches[0].1, [16, 0]);
+    }
+
+    #[test]
+    fn scanner_timeout() {
+        let mut client = ScanClient::new(u32::MAX, 0);
+        let mut scanner = Scanner::<heapless::consts::U8>::new();
+        scanner.poll_interval_ms = 100;
+        scanner.timeout_ms = 300;
+
+        assert!(matches!(scanner.next(&mut client), Some(Err(Err::Timeout))));
+        assert!(scanner.next(&mut client).is_none());
+        assert_eq!(client.delayed_ms, 300);
+        assert!(!client.requests().contains(&ids::WifiRequest::ScanGetNumAPs));
     }
 }